@@ -0,0 +1,46 @@
+-- 刷新令牌族: 每次登录产生一个令牌族, 刷新时在同一族内轮换
+create table token_families
+(
+    id         UUID        not null primary key,
+    user_id    UUID        not null references users (id) on delete cascade,
+    revoked_at TIMESTAMPTZ null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index token_families_user_id_idx on token_families (user_id);
+
+comment
+on table token_families is '刷新令牌族表';
+comment
+on column token_families.id is '主键 (令牌中的 sid)';
+comment
+on column token_families.user_id is '用户id';
+comment
+on column token_families.revoked_at is '注销时间';
+comment
+on column token_families.created_at is '创建时间';
+
+-- 刷新令牌
+create table refresh_tokens
+(
+    id         UUID        not null primary key,
+    family_id  UUID        not null references token_families (id) on delete cascade,
+    expires_at TIMESTAMPTZ not null,
+    rotated_at TIMESTAMPTZ null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index refresh_tokens_family_id_idx on refresh_tokens (family_id);
+
+comment
+on table refresh_tokens is '刷新令牌表';
+comment
+on column refresh_tokens.id is '主键 (令牌中的 jti)';
+comment
+on column refresh_tokens.family_id is '令牌族id';
+comment
+on column refresh_tokens.expires_at is '过期时间';
+comment
+on column refresh_tokens.rotated_at is '轮换时间, 不为空说明已被使用';
+comment
+on column refresh_tokens.created_at is '创建时间';
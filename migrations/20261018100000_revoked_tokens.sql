@@ -0,0 +1,21 @@
+-- 已注销令牌: id 为令牌的 jti 或令牌族的 sid, 过期后即可清理
+create table revoked_tokens
+(
+    id         UUID        not null primary key,
+    user_id    UUID        not null references users (id) on delete cascade,
+    expires_at TIMESTAMPTZ not null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index revoked_tokens_expires_at_idx on revoked_tokens (expires_at);
+
+comment
+on table revoked_tokens is '已注销令牌表';
+comment
+on column revoked_tokens.id is '主键 (令牌的 jti 或令牌族的 sid)';
+comment
+on column revoked_tokens.user_id is '用户id';
+comment
+on column revoked_tokens.expires_at is '过期时间, 过期后可清理';
+comment
+on column revoked_tokens.created_at is '创建时间';
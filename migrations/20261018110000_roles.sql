@@ -0,0 +1,83 @@
+-- 角色
+create table roles
+(
+    id          UUID        not null default gen_random_uuid() primary key,
+    name        varchar     not null unique,
+    description varchar null,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+comment
+on table roles is '角色表';
+comment
+on column roles.id is '主键';
+comment
+on column roles.name is '角色名称';
+comment
+on column roles.description is '描述';
+comment
+on column roles.created_at is '创建时间';
+
+-- 权限
+create table permissions
+(
+    id          UUID        not null default gen_random_uuid() primary key,
+    name        varchar     not null unique,
+    description varchar null,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+comment
+on table permissions is '权限表';
+comment
+on column permissions.id is '主键';
+comment
+on column permissions.name is '权限名称';
+comment
+on column permissions.description is '描述';
+comment
+on column permissions.created_at is '创建时间';
+
+-- 角色权限
+create table role_permissions
+(
+    role_id       UUID not null references roles (id) on delete cascade,
+    permission_id UUID not null references permissions (id) on delete cascade,
+    primary key (role_id, permission_id)
+);
+
+comment
+on table role_permissions is '角色权限关联表';
+
+-- 用户角色
+create table user_roles
+(
+    user_id    UUID        not null references users (id) on delete cascade,
+    role_id    UUID        not null references roles (id) on delete cascade,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    primary key (user_id, role_id)
+);
+
+comment
+on table user_roles is '用户角色关联表';
+
+-- 初始数据
+insert into roles (name, description)
+values ('admin', '管理员'),
+       ('user', '普通用户');
+
+insert into permissions (name, description)
+values ('users:read', '查询其他用户信息'),
+       ('roles:manage', '管理用户角色');
+
+insert into role_permissions (role_id, permission_id)
+select r.id, p.id
+from roles r,
+     permissions p
+where r.name = 'admin';
+
+insert into user_roles (user_id, role_id)
+select u.id, r.id
+from users u,
+     roles r
+where r.name = 'user';
@@ -0,0 +1,47 @@
+-- 两步验证 (TOTP)
+create table user_totp
+(
+    user_id        UUID        not null primary key references users (id) on delete cascade,
+    secret         varchar     not null,
+    last_used_step BIGINT      null,
+    enabled_at     TIMESTAMPTZ null,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+comment
+on table user_totp is '两步验证表';
+comment
+on column user_totp.user_id is '用户id';
+comment
+on column user_totp.secret is '加密后的 TOTP 秘钥';
+comment
+on column user_totp.last_used_step is '最后一次使用的验证码时间步, 防止验证码重放';
+comment
+on column user_totp.enabled_at is '启用时间, 为空说明还未确认绑定';
+comment
+on column user_totp.created_at is '创建时间';
+
+-- 恢复码
+create table recovery_codes
+(
+    id         UUID        not null default gen_random_uuid() primary key,
+    user_id    UUID        not null references users (id) on delete cascade,
+    code_hash  varchar     not null,
+    used_at    TIMESTAMPTZ null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index recovery_codes_user_id_idx on recovery_codes (user_id);
+
+comment
+on table recovery_codes is '两步验证恢复码表';
+comment
+on column recovery_codes.id is '主键';
+comment
+on column recovery_codes.user_id is '用户id';
+comment
+on column recovery_codes.code_hash is '恢复码哈希';
+comment
+on column recovery_codes.used_at is '使用时间, 不为空说明已被使用';
+comment
+on column recovery_codes.created_at is '创建时间';
@@ -0,0 +1,27 @@
+-- 重置密码令牌
+create table password_reset_tokens
+(
+    id         UUID        not null default gen_random_uuid() primary key,
+    user_id    UUID        not null references users (id) on delete cascade,
+    token_hash varchar     not null unique,
+    expires_at TIMESTAMPTZ not null,
+    used_at    TIMESTAMPTZ null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index password_reset_tokens_user_id_idx on password_reset_tokens (user_id);
+
+comment
+on table password_reset_tokens is '重置密码令牌表';
+comment
+on column password_reset_tokens.id is '主键';
+comment
+on column password_reset_tokens.user_id is '用户id';
+comment
+on column password_reset_tokens.token_hash is '令牌哈希, 令牌本身只出现在邮件中';
+comment
+on column password_reset_tokens.expires_at is '过期时间';
+comment
+on column password_reset_tokens.used_at is '使用时间, 不为空说明已被使用';
+comment
+on column password_reset_tokens.created_at is '创建时间';
@@ -0,0 +1,30 @@
+-- 邮箱验证令牌
+create table email_verification_tokens
+(
+    id         UUID        not null default gen_random_uuid() primary key,
+    user_id    UUID        not null references users (id) on delete cascade,
+    email      varchar     not null,
+    token_hash varchar     not null unique,
+    expires_at TIMESTAMPTZ not null,
+    used_at    TIMESTAMPTZ null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index email_verification_tokens_user_id_idx on email_verification_tokens (user_id);
+
+comment
+on table email_verification_tokens is '邮箱验证令牌表';
+comment
+on column email_verification_tokens.id is '主键';
+comment
+on column email_verification_tokens.user_id is '用户id';
+comment
+on column email_verification_tokens.email is '发送验证邮件时的邮箱, 邮箱变更后令牌失效';
+comment
+on column email_verification_tokens.token_hash is '令牌哈希, 令牌本身只出现在邮件中';
+comment
+on column email_verification_tokens.expires_at is '过期时间';
+comment
+on column email_verification_tokens.used_at is '使用时间, 不为空说明已被使用';
+comment
+on column email_verification_tokens.created_at is '创建时间';
@@ -0,0 +1,30 @@
+-- 登录失败记录
+create table login_throttles
+(
+    key             varchar     not null primary key,
+    failures        INTEGER     not null default 0,
+    last_failure_at TIMESTAMPTZ not null default current_timestamp,
+    locked_until    TIMESTAMPTZ null
+);
+
+comment
+on table login_throttles is '登录失败记录表';
+comment
+on column login_throttles.key is '主键, 账号 (account:用户id或登录名) 或 IP (ip:地址)';
+comment
+on column login_throttles.failures is '连续失败次数';
+comment
+on column login_throttles.last_failure_at is '最后一次失败时间';
+comment
+on column login_throttles.locked_until is '锁定截止时间';
+
+-- 管理用户账号权限
+insert into permissions (name, description)
+values ('users:manage', '管理用户账号');
+
+insert into role_permissions (role_id, permission_id)
+select r.id, p.id
+from roles r,
+     permissions p
+where r.name = 'admin'
+  and p.name = 'users:manage';
@@ -0,0 +1,12 @@
+-- 会话: 每个令牌族即一次登录会话, 记录登录设备信息
+alter table token_families
+    add column user_agent   varchar     null,
+    add column ip           varchar     null,
+    add column last_used_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp;
+
+comment
+on column token_families.user_agent is '登录设备的 User-Agent';
+comment
+on column token_families.ip is '最后一次使用时的客户端 IP';
+comment
+on column token_families.last_used_at is '最后一次使用时间 (登录或刷新令牌)';
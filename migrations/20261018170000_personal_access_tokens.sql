@@ -0,0 +1,36 @@
+-- 个人访问令牌: 供脚本/CI 调用接口, 只保存令牌哈希
+create table personal_access_tokens
+(
+    id           UUID        not null default gen_random_uuid() primary key,
+    user_id      UUID        not null references users (id) on delete cascade,
+    name         varchar     not null,
+    token_hash   varchar     not null unique,
+    scopes       varchar[]   not null default '{}',
+    expires_at   TIMESTAMPTZ null,
+    last_used_at TIMESTAMPTZ null,
+    revoked_at   TIMESTAMPTZ null,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index personal_access_tokens_user_id_idx on personal_access_tokens (user_id);
+
+comment
+on table personal_access_tokens is '个人访问令牌表';
+comment
+on column personal_access_tokens.id is '主键';
+comment
+on column personal_access_tokens.user_id is '用户id';
+comment
+on column personal_access_tokens.name is '令牌名称';
+comment
+on column personal_access_tokens.token_hash is '令牌哈希';
+comment
+on column personal_access_tokens.scopes is '权限范围, 只能是用户拥有的权限';
+comment
+on column personal_access_tokens.expires_at is '过期时间, 为空说明永不过期';
+comment
+on column personal_access_tokens.last_used_at is '最后一次使用时间';
+comment
+on column personal_access_tokens.revoked_at is '注销时间';
+comment
+on column personal_access_tokens.created_at is '创建时间';
@@ -0,0 +1,31 @@
+-- 第三方账号: 通过 OpenID Connect 登录的账号与用户的绑定关系
+create table external_identities
+(
+    id            UUID        not null default gen_random_uuid() primary key,
+    user_id       UUID        not null references users (id) on delete cascade,
+    provider      varchar     not null,
+    subject       varchar     not null,
+    email         varchar     null,
+    last_login_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    unique (provider, subject)
+);
+
+create index external_identities_user_id_idx on external_identities (user_id);
+
+comment
+on table external_identities is '第三方账号表';
+comment
+on column external_identities.id is '主键';
+comment
+on column external_identities.user_id is '用户id';
+comment
+on column external_identities.provider is '身份提供方名称';
+comment
+on column external_identities.subject is '用户在身份提供方的唯一标识 (id_token 中的 sub)';
+comment
+on column external_identities.email is '身份提供方返回的邮箱';
+comment
+on column external_identities.last_login_at is '最后一次登录时间';
+comment
+on column external_identities.created_at is '创建时间';
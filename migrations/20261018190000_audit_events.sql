@@ -0,0 +1,74 @@
+-- 审计日志: 登录/注册/修改凭据等账号安全事件, 只允许追加
+create table audit_events
+(
+    id         UUID        not null default gen_random_uuid() primary key,
+    action     varchar     not null,
+    outcome    varchar     not null,
+    actor_id   UUID        null,
+    subject_id UUID        null,
+    login      varchar     null,
+    ip         varchar     null,
+    user_agent varchar     null,
+    metadata   jsonb       not null default '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+create index audit_events_created_at_idx on audit_events (created_at desc);
+create index audit_events_actor_id_idx on audit_events (actor_id);
+create index audit_events_subject_id_idx on audit_events (subject_id);
+create index audit_events_action_idx on audit_events (action);
+
+comment
+on table audit_events is '审计日志表';
+comment
+on column audit_events.id is '主键';
+comment
+on column audit_events.action is '事件类型, 例如 sign_in / register / password_change';
+comment
+on column audit_events.outcome is '结果: success / failure';
+comment
+on column audit_events.actor_id is '操作人id, 未登录时为空';
+comment
+on column audit_events.subject_id is '被操作的用户id';
+comment
+on column audit_events.login is '登录失败时提交的登录名';
+comment
+on column audit_events.ip is '客户端 IP';
+comment
+on column audit_events.user_agent is '客户端 User-Agent';
+comment
+on column audit_events.metadata is '附加信息';
+comment
+on column audit_events.created_at is '创建时间';
+
+-- 不允许修改和删除 (用户删除后也保留审计日志, 所以不设置外键)
+create function audit_events_append_only() returns trigger
+    language plpgsql as
+$$
+begin
+    raise exception '审计日志只允许追加';
+end;
+$$;
+
+create trigger audit_events_no_update_delete
+    before update or delete
+    on audit_events
+    for each row
+execute function audit_events_append_only();
+
+create trigger audit_events_no_truncate
+    before truncate
+    on audit_events
+    for each statement
+execute function audit_events_append_only();
+
+-- 查询审计日志权限
+insert into permissions (name, description)
+values ('audit:read', '查询审计日志');
+
+insert into role_permissions (role_id, permission_id)
+select r.id, p.id
+from roles r,
+     permissions p
+where r.name = 'admin'
+  and p.name = 'audit:read';
@@ -0,0 +1,10 @@
+-- 代登录权限: 管理员以指定用户身份登录排查问题
+insert into permissions (name, description)
+values ('users:impersonate', '代登录其他用户');
+
+insert into role_permissions (role_id, permission_id)
+select r.id, p.id
+from roles r,
+     permissions p
+where r.name = 'admin'
+  and p.name = 'users:impersonate';
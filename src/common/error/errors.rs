@@ -1,17 +1,23 @@
+use std::collections::BTreeMap;
 use std::convert::Infallible;
 
-use async_graphql::{Error as AgError, ErrorExtensions};
+use async_graphql::{Error as AgError, ErrorExtensions, Name, Value};
 use async_graphql_warp::BadRequest;
 use thiserror::Error;
 use validator::ValidationErrors;
 use warp::{hyper::StatusCode, reply::WithStatus};
 
+use crate::security::password_policy::PasswordViolation;
+
 /// 定义错误枚举
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("服务器内部错误")]
     InternalError,
 
+    #[error("服务器繁忙, 请稍后重试")]
+    ServerBusy,
+
     #[error("客户端错误")]
     ClientError,
 
@@ -26,6 +32,72 @@ pub enum AppError {
 
     #[error("用户名或密码错误")]
     UsernameOrPasswordError,
+
+    #[error("刷新令牌无效或已过期")]
+    InvalidRefreshToken,
+
+    #[error("刷新令牌已被使用, 请重新登录")]
+    RefreshTokenReused,
+
+    #[error("令牌类型错误")]
+    TokenTypeMismatch,
+
+    #[error("未登录或登录已过期")]
+    Unauthenticated,
+
+    #[error("没有访问权限")]
+    Forbidden,
+
+    #[error("已启用两步验证")]
+    MfaAlreadyEnabled,
+
+    #[error("未启用两步验证")]
+    MfaNotEnabled,
+
+    #[error("验证码错误")]
+    InvalidMfaCode,
+
+    #[error("两步验证已过期, 请重新登录")]
+    InvalidMfaToken,
+
+    #[error("重置密码链接无效或已过期")]
+    InvalidPasswordResetToken,
+
+    #[error("邮箱验证链接无效或已过期")]
+    InvalidEmailVerificationToken,
+
+    #[error("邮箱未验证, 请先验证邮箱")]
+    EmailNotVerified,
+
+    #[error("登录失败次数过多, 请 {0} 秒后重试")]
+    TooManyAttempts(i64),
+
+    #[error("无效的权限范围: {0}")]
+    InvalidScope(String),
+
+    #[error("第三方登录失败, 请重新登录")]
+    ExternalLoginFailed,
+
+    #[error("第三方账号未绑定用户")]
+    ExternalAccountNotLinked,
+
+    #[error("密码不符合安全要求")]
+    WeakPassword(Vec<PasswordViolation>),
+
+    #[error("代登录时不能执行此操作")]
+    ImpersonationForbidden,
+
+    #[error("不能代登录该用户")]
+    CannotImpersonate,
+
+    #[error("需要重新验证密码")]
+    ReauthenticationRequired,
+
+    #[error("账号已停用")]
+    AccountDisabled,
+
+    #[error("密码由外部目录管理, 请在目录中修改")]
+    ExternalPassword,
 }
 
 // warp 错误处理
@@ -54,6 +126,14 @@ impl AppError {
         })
     }
 
+    /// 转换 service 层返回的错误, 业务错误直接返回给客户端, 其他错误输出日志后按服务器内部错误处理
+    pub fn service_extend(error: anyhow::Error) -> AgError {
+        match error.downcast::<AppError>() {
+            Ok(error) => error.extend(),
+            Err(error) => AppError::InternalError.log_extend()(error),
+        }
+    }
+
     /// 返回错误扩展并输出日志的闭包
     pub fn validation_extend(self) -> Box<dyn FnOnce(ValidationErrors) -> AgError> {
         Box::new(move |error| {
@@ -77,11 +157,50 @@ impl ErrorExtensions for AppError {
             match error {
                 // 在返回给客户端的新增中新增了 code 业务状态码, 作为业务状态梳理
                 AppError::InternalError => e.set("code", "B0001"),
+                AppError::ServerBusy => e.set("code", "B0002"),
                 AppError::ClientError => e.set("code", "A0001"),
                 AppError::RequestParameterError => e.set("code", "A0002"),
                 AppError::UsernameAlreadyExists => e.set("code", "A0003"),
                 AppError::EmailAlreadyExists => e.set("code", "A0004"),
                 AppError::UsernameOrPasswordError => e.set("code", "A0005"),
+                AppError::InvalidRefreshToken => e.set("code", "A0006"),
+                AppError::RefreshTokenReused => e.set("code", "A0007"),
+                AppError::TokenTypeMismatch => e.set("code", "A0008"),
+                AppError::Unauthenticated => e.set("code", "A0009"),
+                AppError::Forbidden => e.set("code", "A0010"),
+                AppError::MfaAlreadyEnabled => e.set("code", "A0011"),
+                AppError::MfaNotEnabled => e.set("code", "A0012"),
+                AppError::InvalidMfaCode => e.set("code", "A0013"),
+                AppError::InvalidMfaToken => e.set("code", "A0014"),
+                AppError::InvalidPasswordResetToken => e.set("code", "A0015"),
+                AppError::InvalidEmailVerificationToken => e.set("code", "A0016"),
+                AppError::EmailNotVerified => e.set("code", "A0017"),
+                AppError::TooManyAttempts(retry_after) => {
+                    e.set("code", "A0018");
+                    e.set("retryAfter", *retry_after);
+                }
+                AppError::InvalidScope(_) => e.set("code", "A0019"),
+                AppError::ExternalLoginFailed => e.set("code", "A0020"),
+                AppError::ExternalAccountNotLinked => e.set("code", "A0021"),
+                AppError::WeakPassword(violations) => {
+                    e.set("code", "A0022");
+                    // 返回每条不符合的规则: [{rule, message}]
+                    let violations = violations
+                        .iter()
+                        .map(|violation| {
+                            let mut map = BTreeMap::new();
+                            map.insert(Name::new("rule"), Value::from(violation.rule()));
+                            map.insert(Name::new("message"), Value::from(violation.to_string()));
+                            Value::Object(map)
+                        })
+                        .collect::<Vec<_>>();
+                    e.set("violations", violations);
+                }
+                AppError::ImpersonationForbidden => e.set("code", "A0023"),
+                AppError::CannotImpersonate => e.set("code", "A0024"),
+                AppError::ReauthenticationRequired => e.set("code", "A0025"),
+                AppError::AccountDisabled => e.set("code", "A0026"),
+                AppError::ExternalPassword => e.set("code", "A0027"),
             }
         })
     }
@@ -99,3 +218,14 @@ impl ErrorExtensions for AppError {
         }
     }
 }
+
+/// 结果是否为指定的业务错误, 只比较错误类型, 不比较错误中的值
+#[cfg(test)]
+pub fn is_app_error<T>(result: &anyhow::Result<T>, expected: AppError) -> bool {
+    match result {
+        Err(error) => error.downcast_ref::<AppError>().is_some_and(|error| {
+            std::mem::discriminant(error) == std::mem::discriminant(&expected)
+        }),
+        Ok(_) => false,
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// 邮件
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// 邮件发送器, 通过配置文件选择实现
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    /// 发送邮件
+    async fn send(&self, mail: Mail) -> Result<()>;
+}
+
+/// 把邮件写入目录, 每封邮件一个 `.eml` 文件, 用于开发环境
+#[derive(Debug)]
+pub struct FileMailSender {
+    pub from: String,
+    pub dir: PathBuf,
+}
+
+#[async_trait]
+impl MailSender for FileMailSender {
+    async fn send(&self, mail: Mail) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context(format!("创建邮件目录:[{}] 失败!", self.dir.display()))?;
+
+        let now = Utc::now();
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            now.format("%Y%m%d%H%M%S"),
+            Uuid::new_v4()
+        ));
+        let content = format!(
+            "From: {}\r\nTo: {}\r\nSubject: =?UTF-8?B?{}?=\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from,
+            mail.to,
+            // 非 ASCII 标题按 RFC 2047 编码
+            base64::encode(&mail.subject),
+            now.to_rfc2822(),
+            mail.body
+        );
+        tokio::fs::write(&path, content)
+            .await
+            .context(format!("写入邮件:[{}] 失败!", path.display()))?;
+        log::info!("邮件: [{}] 已写入: [{}]", mail.subject, path.display());
+        Ok(())
+    }
+}
+
+/// 把邮件保存在内存中, 用于测试
+#[derive(Debug, Default)]
+pub struct MemoryMailSender {
+    outbox: Mutex<Vec<Mail>>,
+}
+
+impl MemoryMailSender {
+    /// 已发送的邮件
+    pub fn outbox(&self) -> Vec<Mail> {
+        self.outbox.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl MailSender for MemoryMailSender {
+    async fn send(&self, mail: Mail) -> Result<()> {
+        self.outbox.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_mail_sender() {
+    let mail = Mail {
+        to: "alice@example.com".to_string(),
+        subject: "重置密码".to_string(),
+        body: "https://example.com/reset-password?token=abc".to_string(),
+    };
+
+    let memory = MemoryMailSender::default();
+    memory.send(mail.clone()).await.unwrap();
+    assert_eq!(memory.outbox().len(), 1);
+    assert_eq!(memory.outbox()[0].to, mail.to);
+
+    let dir = std::env::temp_dir().join(format!("mail-{}", Uuid::new_v4()));
+    let file = FileMailSender {
+        from: "noreply@example.com".to_string(),
+        dir: dir.clone(),
+    };
+    file.send(mail.clone()).await.unwrap();
+    let entry = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+    let content = std::fs::read_to_string(entry.path()).unwrap();
+    assert!(content.contains("To: alice@example.com"));
+    assert!(content.contains("Subject: =?UTF-8?B?6YeN572u5a+G56CB?="));
+    assert!(content.contains(&mail.body));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,10 +1,23 @@
-use crate::security::crypto::CryptoService;
-use anyhow::Context;
+use crate::common::mail::mailer::{FileMailSender, MailSender, MemoryMailSender};
+use crate::security::breach::BreachedPasswords;
+use crate::security::crypto::{dummy_password_hash, CryptoService, HashParams};
+use crate::security::hash_pool::HashPool;
+use crate::security::keys::{JwtKey, JwtKeyring};
+use crate::security::oidc::OidcProviders;
+use crate::security::provider::{AuthProviders, LOCAL_PROVIDER};
+use anyhow::{bail, Context};
+use argon2::Variant;
+use config::Value;
+use jsonwebtoken::Algorithm;
 use log::LevelFilter;
 use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::{ConnectOptions, Pool, Postgres};
+use std::collections::HashMap;
+use std::path::Path;
 use std::{any::type_name, env::current_dir};
 use std::{net::SocketAddrV4, sync::Arc};
 use std::{path::PathBuf, time::Duration};
@@ -28,6 +41,36 @@ pub const SEPARATOR: &str = "_";
 /// 默认健康检查地址
 pub const HEALTH_CHECK: &str = "/health_check";
 
+/// 生产环境标识, 启动时检查秘钥是否安全
+pub const PRODUCTION_ENVIRONMENT: &str = "prod";
+
+/// 保存秘钥的配置项, 都可以改为从文件读取 (例如 Docker/Kubernetes secrets):
+/// 配置文件中使用 `<配置项>_file = "/run/secrets/..."`, 环境变量见 [`secret_file_key`].
+/// `crypto.hash.salt` 只用于识别旧的密码哈希, 不是秘钥
+const SECRET_KEYS: [&str; 5] = [
+    "password",
+    "secret",
+    "encryption_key",
+    "bind_password",
+    "client_secret",
+];
+
+/// 从文件读取秘钥的配置项后缀
+const SECRET_FILE_SUFFIX: &str = "_file";
+
+/// 示例配置中的占位值, 生产环境不能使用
+const PLACEHOLDER_SECRETS: [&str; 6] = [
+    "替换为真正key",
+    "your-256-bit-secret",
+    "secret",
+    "123456",
+    "admin",
+    "changeme",
+];
+
+/// 生产环境签名/加密秘钥的最小长度 (256 位)
+const MIN_SECRET_LENGTH: usize = 32;
+
 /// 配置项结构体
 #[derive(Deserialize, Clone, Debug)]
 pub struct Configs {
@@ -36,6 +79,8 @@ pub struct Configs {
     pub database: DatabaseConfig,
     pub log: LogConfig,
     pub crypto: CryptoConfig,
+    pub auth: AuthConfig,
+    pub mail: MailConfig,
 }
 
 impl Configs {
@@ -63,13 +108,206 @@ impl Configs {
 
         // 从环境变量或.env中添加设置（以APP前缀和'__'作为分隔符）
         // APP_SERVER_PORT = 5001 将覆盖 ApplicationConfig.server.port
-        settings.merge(config::Environment::with_prefix(SERVER_PREFIX).separator(SEPARATOR))?;
+        settings.merge(EnvironmentSource)?;
+        // 环境变量指定的秘钥文件, 转换为 `<配置项>_file` 后和配置文件中的一起读取
+        for (name, path) in std::env::vars() {
+            if let Some(key) = secret_file_key(&name) {
+                settings.set(&key, path)?;
+            }
+        }
+
+        // 读取秘钥文件后转换为配置文件结构体
+        let settings = settings.try_into().context("配置文件转换错误!")?;
+        let settings = resolve_secret_files(settings, &config_dir)?;
+        let config: Configs = settings.try_into().context("配置文件转换错误!")?;
 
-        // 将读取的配置文件转换为配置文件结构体
-        let config = settings.try_into().context("配置文件转换错误!")?;
+        if environment == PRODUCTION_ENVIRONMENT {
+            config.check_secrets()?;
+        }
 
         Ok(Arc::new(config))
     }
+
+    /// 检查秘钥, 不能使用示例配置中的占位值, 签名/加密秘钥不能过短
+    pub fn check_secrets(&self) -> anyhow::Result<()> {
+        let crypto = &self.crypto;
+        let mut secrets = vec![
+            ("database.password", Some(&self.database.password), 0),
+            (
+                "crypto.hash.secret",
+                Some(&crypto.hash.secret),
+                MIN_SECRET_LENGTH,
+            ),
+            (
+                "crypto.mfa.encryption_key",
+                Some(&crypto.mfa.encryption_key),
+                MIN_SECRET_LENGTH,
+            ),
+        ];
+        // 配置秘钥列表后忽略单个秘钥, 已停用的秘钥不会加载
+        if crypto.jwt.keys.is_empty() {
+            if crypto.jwt.algorithm == Algorithm::HS256 {
+                secrets.push((
+                    "crypto.jwt.secret",
+                    Some(&crypto.jwt.secret),
+                    MIN_SECRET_LENGTH,
+                ));
+            }
+        } else {
+            for key in &crypto.jwt.keys {
+                if key.algorithm == Algorithm::HS256 && !key.retired {
+                    secrets.push((
+                        "crypto.jwt.keys.secret",
+                        key.secret.as_ref(),
+                        MIN_SECRET_LENGTH,
+                    ));
+                }
+            }
+        }
+        for ldap in &self.auth.ldap {
+            if ldap.bind_dn.is_some() {
+                secrets.push(("auth.ldap.bind_password", ldap.bind_password.as_ref(), 0));
+            }
+        }
+        for provider in &self.auth.oidc.providers {
+            if provider.client_secret.is_some() {
+                secrets.push((
+                    "auth.oidc.providers.client_secret",
+                    provider.client_secret.as_ref(),
+                    0,
+                ));
+            }
+        }
+
+        let problems = secrets
+            .into_iter()
+            .filter_map(|(name, value, min_length)| {
+                check_secret(value.map(String::as_str), min_length)
+                    .map(|problem| format!("[{}] {}", name, problem))
+            })
+            .collect::<Vec<_>>();
+        if !problems.is_empty() {
+            bail!("生产环境的秘钥不安全: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+}
+
+/// 检查单个秘钥, 返回不安全的原因
+fn check_secret(value: Option<&str>, min_length: usize) -> Option<String> {
+    let value = match value.map(str::trim) {
+        Some(value) if !value.is_empty() => value,
+        _ => return Some("未配置".to_string()),
+    };
+    if PLACEHOLDER_SECRETS
+        .iter()
+        .any(|placeholder| value.eq_ignore_ascii_case(placeholder))
+    {
+        return Some("仍在使用示例配置中的值".to_string());
+    }
+    if value.len() < min_length {
+        return Some(format!("长度不能少于 {} 个字符", min_length));
+    }
+    None
+}
+
+/// 环境变量覆盖配置, 与 `config::Environment` 相同, 但不包括秘钥文件环境变量
+///
+/// 秘钥文件环境变量按分隔符解析会得到 `secret.file` 这样的配置项, 数组中的配置项还会把数组替换为对象,
+/// 由 [`secret_file_key`] 单独解析
+#[derive(Clone, Debug)]
+struct EnvironmentSource;
+
+impl config::Source for EnvironmentSource {
+    fn clone_into_box(&self) -> Box<dyn config::Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, config::ConfigError> {
+        let environment = config::Environment::with_prefix(SERVER_PREFIX).separator(SEPARATOR);
+        let mut values = config::Source::collect(&environment)?;
+        values.retain(|key, _| {
+            let name = format!(
+                "{}{}{}",
+                SERVER_PREFIX,
+                SEPARATOR,
+                key.replace('.', SEPARATOR)
+            );
+            secret_file_key(&name).is_none()
+        });
+        Ok(values)
+    }
+}
+
+/// 秘钥文件环境变量对应的配置项, 不是秘钥文件环境变量时返回 `None`
+///
+/// 环境变量格式为 `SERVER_<上级配置项>_<秘钥配置项>_FILE`, 秘钥配置项按 [`SECRET_KEYS`] 匹配,
+/// 可以包含下划线; 上级配置项按下划线分隔, 数字为数组下标 (上级配置项名称不能包含下划线), 例如:
+/// - `SERVER_CRYPTO_JWT_SECRET_FILE` -> `crypto.jwt.secret_file`
+/// - `SERVER_CRYPTO_MFA_ENCRYPTION_KEY_FILE` -> `crypto.mfa.encryption_key_file`
+/// - `SERVER_AUTH_OIDC_PROVIDERS_0_CLIENT_SECRET_FILE` -> `auth.oidc.providers[0].client_secret_file`
+fn secret_file_key(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    let prefix = format!("{}{}", SERVER_PREFIX, SEPARATOR).to_lowercase();
+    let name = name
+        .strip_prefix(&prefix)?
+        .strip_suffix(SECRET_FILE_SUFFIX)?;
+    // secret 和 client_secret 都能匹配时取较长的
+    let key = SECRET_KEYS
+        .iter()
+        .filter(|key| name == **key || name.ends_with(&format!("{}{}", SEPARATOR, key)))
+        .max_by_key(|key| key.len())?;
+
+    let mut path = String::new();
+    let parent = name[..name.len() - key.len()].trim_end_matches(SEPARATOR);
+    for segment in parent
+        .split(SEPARATOR)
+        .filter(|segment| !segment.is_empty())
+    {
+        if segment.bytes().all(|byte| byte.is_ascii_digit()) {
+            path.push_str(&format!("[{}]", segment));
+        } else {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+    }
+    if !path.is_empty() {
+        path.push('.');
+    }
+    Some(format!("{}{}{}", path, key, SECRET_FILE_SUFFIX))
+}
+
+/// 读取秘钥文件, 文件中的秘钥优先于直接配置的值, 相对路径基于配置文件目录
+///
+/// 配置项 `secret_file = "..."` (包括数组中的配置项) 会读取文件内容作为 `secret` 的值
+fn resolve_secret_files(value: Value, config_dir: &Path) -> anyhow::Result<Value> {
+    if let Ok(mut table) = value.clone().into_table() {
+        for key in SECRET_KEYS.iter() {
+            let file_key = format!("{}{}", key, SECRET_FILE_SUFFIX);
+            if let Some(path) = table.remove(&file_key) {
+                let path = config_dir.join(path.into_str()?);
+                let secret = std::fs::read_to_string(&path)
+                    .context(format!("读取秘钥文件:[{}] 失败!", path.display()))?;
+                let secret = secret.trim_end_matches(&['\r', '\n'][..]).to_string();
+                table.insert(key.to_string(), Value::new(None, secret));
+            }
+        }
+        let table = table
+            .into_iter()
+            .map(|(key, value)| Ok((key, resolve_secret_files(value, config_dir)?)))
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        return Ok(Value::new(None, table));
+    }
+    if let Ok(array) = value.clone().into_array() {
+        let array = array
+            .into_iter()
+            .map(|value| resolve_secret_files(value, config_dir))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        return Ok(Value::new(None, array));
+    }
+    Ok(value)
 }
 
 /// 服务配置
@@ -79,6 +317,9 @@ pub struct ServerConfig {
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+    /// 是否部署在反向代理之后, 为 true 时使用 `X-Forwarded-For` 中反向代理追加的最后一个地址作为客户端地址
+    #[serde(default)]
+    pub trust_proxy: bool,
 }
 
 impl ServerConfig {
@@ -158,22 +399,97 @@ impl LogConfig {
 pub struct CryptoConfig {
     pub hash: HashConfig,
     pub jwt: JwtConfig,
+    pub mfa: MfaConfig,
 }
 
 /// 加密服务相关配置
 #[derive(Deserialize, Clone, Debug)]
 pub struct HashConfig {
-    /// 密码盐
+    /// 旧版本的全局密码盐, 仅用于识别需要升级的密码哈希
     pub salt: String,
     /// 秘钥
     pub secret: String,
+    /// argon2 算法
+    #[serde(default)]
+    pub variant: HashVariant,
+    /// 内存开销 (KiB)
+    #[serde(
+        default = "default_mem_cost",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub mem_cost: u32,
+    /// 迭代次数
+    #[serde(
+        default = "default_time_cost",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub time_cost: u32,
+    /// 并行度
+    #[serde(
+        default = "default_lanes",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub lanes: u32,
+    /// 密码哈希计算线程数量 (即同时进行的计算数量), 默认为 CPU 核数
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub concurrency: Option<usize>,
+    /// 密码哈希计算排队超时时间, 超时返回服务器繁忙
+    #[serde(with = "humantime_serde", default)]
+    pub queue_timeout: Option<Duration>,
+}
+
+/// argon2 算法
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HashVariant {
+    Argon2d,
+    #[default]
+    Argon2i,
+    Argon2id,
+}
+
+impl From<HashVariant> for Variant {
+    fn from(variant: HashVariant) -> Self {
+        match variant {
+            HashVariant::Argon2d => Variant::Argon2d,
+            HashVariant::Argon2i => Variant::Argon2i,
+            HashVariant::Argon2id => Variant::Argon2id,
+        }
+    }
+}
+
+/// 内存开销默认值 (与 argon2 默认值一致)
+fn default_mem_cost() -> u32 {
+    4096
+}
+
+/// 迭代次数默认值 (与 argon2 默认值一致)
+fn default_time_cost() -> u32 {
+    3
+}
+
+/// 并行度默认值 (与 argon2 默认值一致)
+fn default_lanes() -> u32 {
+    1
 }
 
 /// jwt相关配置
 #[derive(Deserialize, Clone, Debug)]
 pub struct JwtConfig {
-    /// 秘钥
+    /// 秘钥, 签名算法为 HS256 时使用
     pub secret: String,
+    /// 签名算法: HS256 / RS256 / ES256 / EdDSA
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// 非对称签名算法的私钥文件 (PEM), 相对路径基于配置文件目录
+    pub private_key_file: Option<String>,
+    /// 秘钥id, 写入 jwt 头部的 `kid`
+    pub kid: Option<String>,
+    /// 轮换秘钥时使用的秘钥列表, 配置后忽略上面的单个秘钥
+    #[serde(default)]
+    pub keys: Vec<JwtKeyConfig>,
+    /// 签发令牌使用的秘钥id, 为空时使用秘钥列表中第一个未停用的秘钥
+    pub active_kid: Option<String>,
     /// 访问token过期时间
     #[serde(with = "humantime_serde", default)]
     pub access_expires: Option<Duration>,
@@ -183,6 +499,93 @@ pub struct JwtConfig {
     /// 签发人
     #[serde(default = "default_issuer")]
     pub issuer: String,
+    /// 受众
+    #[serde(default = "default_audience")]
+    pub audience: String,
+    /// 已注销令牌清理间隔
+    #[serde(with = "humantime_serde", default)]
+    pub purge_interval: Option<Duration>,
+    /// 管理员代登录令牌过期时间
+    #[serde(with = "humantime_serde", default)]
+    pub impersonation_expires: Option<Duration>,
+}
+
+impl JwtConfig {
+    /// 加载签名秘钥环, 已停用的秘钥不会加载, 其签发的令牌验证失败
+    pub fn load_keyring(&self) -> anyhow::Result<JwtKeyring> {
+        if self.keys.is_empty() {
+            let key = load_key(
+                self.kid.clone(),
+                self.algorithm,
+                Some(&self.secret),
+                self.private_key_file.as_ref(),
+            )?;
+            return Ok(JwtKeyring::single(key));
+        }
+
+        let keys = self
+            .keys
+            .iter()
+            .filter(|key| !key.retired)
+            .map(|key| {
+                load_key(
+                    Some(key.kid.clone()),
+                    key.algorithm,
+                    key.secret.as_ref(),
+                    key.private_key_file.as_ref(),
+                )
+                .context(format!("加载 jwt 秘钥:[{}] 失败!", key.kid))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        JwtKeyring::new(keys, self.active_kid.as_deref())
+    }
+
+    /// 获取已注销令牌清理间隔
+    pub fn get_purge_interval(&self) -> Duration {
+        self.purge_interval
+            .unwrap_or_else(|| Duration::from_secs(10 * 60))
+    }
+}
+
+/// jwt秘钥配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct JwtKeyConfig {
+    /// 秘钥id
+    pub kid: String,
+    /// 签名算法: HS256 / RS256 / ES256 / EdDSA
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// 秘钥, 签名算法为 HS256 时使用
+    pub secret: Option<String>,
+    /// 非对称签名算法的私钥文件 (PEM), 相对路径基于配置文件目录
+    pub private_key_file: Option<String>,
+    /// 是否已停用, 停用后该秘钥签发的令牌全部失效
+    #[serde(default)]
+    pub retired: bool,
+}
+
+/// 加载签名秘钥
+fn load_key(
+    kid: Option<String>,
+    algorithm: Algorithm,
+    secret: Option<&String>,
+    private_key_file: Option<&String>,
+) -> anyhow::Result<JwtKey> {
+    match algorithm {
+        Algorithm::HS256 => {
+            let secret = secret.context("签名算法:[HS256] 需要配置秘钥")?;
+            Ok(JwtKey::from_secret(kid, secret))
+        }
+        algorithm => {
+            let file =
+                private_key_file.context(format!("签名算法:[{:?}] 需要配置私钥文件", algorithm))?;
+            let path = get_config_dir()?.join(file);
+            let pem =
+                std::fs::read(&path).context(format!("读取私钥文件:[{}] 失败!", path.display()))?;
+            JwtKey::from_private_pem(kid, algorithm, &pem)
+                .context(format!("加载私钥文件:[{}] 失败!", path.display()))
+        }
+    }
 }
 
 /// 签发人默认值
@@ -190,9 +593,27 @@ fn default_issuer() -> String {
     "Server".to_string()
 }
 
+/// 受众默认值
+fn default_audience() -> String {
+    "Server".to_string()
+}
+
+/// 两步验证相关配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct MfaConfig {
+    /// 加密 TOTP 秘钥的秘钥, 实际使用其 SHA-256 摘要作为 AES-256-GCM 秘钥
+    pub encryption_key: String,
+    /// 身份验证器 App 中显示的签发方
+    #[serde(default = "default_issuer")]
+    pub issuer: String,
+    /// 两步验证令牌过期时间
+    #[serde(with = "humantime_serde", default)]
+    pub challenge_expires: Option<Duration>,
+}
+
 impl CryptoConfig {
     /// 获取加密服务
-    pub fn get_crypto_server(&self) -> Arc<CryptoService> {
+    pub fn get_crypto_server(&self) -> anyhow::Result<Arc<CryptoService>> {
         let access_expires = self
             .jwt
             .access_expires
@@ -203,19 +624,456 @@ impl CryptoConfig {
             .refash_expires
             .unwrap_or_else(|| Duration::from_secs(30 * 60 * 7));
 
+        let concurrency = self.hash.concurrency.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        let queue_timeout = self
+            .hash
+            .queue_timeout
+            .unwrap_or_else(|| Duration::from_secs(5));
+
+        let mfa_expires = self
+            .mfa
+            .challenge_expires
+            .unwrap_or_else(|| Duration::from_secs(5 * 60));
+
+        let impersonation_expires = self
+            .jwt
+            .impersonation_expires
+            .unwrap_or_else(|| Duration::from_secs(15 * 60));
+
+        let hash_params = HashParams {
+            variant: self.hash.variant.into(),
+            mem_cost: self.hash.mem_cost,
+            time_cost: self.hash.time_cost,
+            lanes: self.hash.lanes,
+        };
+        let dummy_hash = dummy_password_hash(&self.hash.secret, &hash_params)?;
+
         let crypto = CryptoService {
             hash_salt: Arc::new(self.hash.salt.clone()),
             hash_secret: Arc::new(self.hash.secret.clone()),
-            jwt_secret: Arc::new(self.jwt.secret.clone()),
+            hash_params: Arc::new(hash_params),
+            dummy_hash: Arc::new(dummy_hash),
+            hash_pool: Arc::new(HashPool::new(concurrency, queue_timeout)),
+            jwt_keys: Arc::new(self.jwt.load_keyring()?),
             access_expires: Arc::new(chrono::Duration::from_std(access_expires).unwrap()),
             refash_expires: Arc::new(chrono::Duration::from_std(refash_expires).unwrap()),
             issuer: Arc::new(self.jwt.issuer.clone()),
+            audience: Arc::new(self.jwt.audience.clone()),
+            encryption_key: Arc::new(openssl::sha::sha256(self.mfa.encryption_key.as_bytes())),
+            mfa_expires: Arc::new(chrono::Duration::from_std(mfa_expires).unwrap()),
+            impersonation_expires: Arc::new(
+                chrono::Duration::from_std(impersonation_expires).unwrap(),
+            ),
         };
         log::info!(
             "初始化 '加密服务: [{}]' 完成!",
             type_name::<CryptoService>()
         );
-        Arc::new(crypto)
+        Ok(Arc::new(crypto))
+    }
+}
+
+/// 认证相关配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct AuthConfig {
+    /// 重置密码链接, `{token}` 替换为重置密码令牌
+    pub password_reset_url: String,
+    /// 重置密码令牌过期时间
+    #[serde(with = "humantime_serde", default)]
+    pub password_reset_expires: Option<Duration>,
+    /// 邮箱验证链接, `{token}` 替换为邮箱验证令牌
+    pub email_verification_url: String,
+    /// 邮箱验证令牌过期时间
+    #[serde(with = "humantime_serde", default)]
+    pub email_verification_expires: Option<Duration>,
+    /// 是否要求验证邮箱后才能登录
+    #[serde(default)]
+    pub require_email_verification: bool,
+    /// 验证密码后可以执行敏感操作 (修改两步验证/创建访问令牌等) 的时长, 超过后需要重新验证密码
+    #[serde(with = "humantime_serde", default)]
+    pub reauthentication_window: Option<Duration>,
+    /// 登录失败限制
+    pub throttle: ThrottleConfig,
+    /// 查询用户名是否存在
+    #[serde(default)]
+    pub existence_check: ExistenceCheckConfig,
+    /// 密码策略, 注册/修改密码/重置密码时检查
+    pub password_policy: PasswordPolicyConfig,
+    /// 第三方 (OpenID Connect) 登录
+    #[serde(default)]
+    pub oidc: OidcConfig,
+    /// 用户名密码登录时按顺序尝试的身份验证方式, `local` 为本地用户表, 其他为 LDAP 目录名称
+    #[serde(default = "default_sign_in_providers")]
+    pub sign_in_providers: Vec<String>,
+    /// LDAP 目录
+    #[serde(default)]
+    pub ldap: Vec<LdapConfig>,
+}
+
+/// 用户名密码登录的身份验证方式默认值
+fn default_sign_in_providers() -> Vec<String> {
+    vec![LOCAL_PROVIDER.into()]
+}
+
+/// LDAP 目录配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct LdapConfig {
+    /// 名称, 在 `sign_in_providers` 中引用
+    pub name: String,
+    /// 地址, 例如 `ldap://localhost:389` / `ldaps://ldap.example.com`
+    pub url: String,
+    /// 是否使用 StartTLS
+    #[serde(default)]
+    pub starttls: bool,
+    /// 查询用户时使用的账号, 为空时匿名查询
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    /// 查询用户的根节点
+    pub base_dn: String,
+    /// 查询用户的过滤器, `{login}` 替换为 (转义后的) 登录名
+    #[serde(default = "default_ldap_user_filter")]
+    pub user_filter: String,
+    /// 用户名属性
+    #[serde(default = "default_ldap_username_attribute")]
+    pub username_attribute: String,
+    /// 邮箱属性
+    #[serde(default = "default_ldap_email_attribute")]
+    pub email_attribute: String,
+    /// 昵称属性
+    #[serde(default = "default_ldap_name_attribute")]
+    pub name_attribute: String,
+    /// 超时时间, 包括连接和验证过程
+    #[serde(with = "humantime_serde", default)]
+    pub timeout: Option<Duration>,
+    /// 目录用户首次登录时是否自动创建用户
+    #[serde(default)]
+    pub auto_provision: bool,
+    /// 是否按邮箱绑定已有用户
+    #[serde(default)]
+    pub link_by_email: bool,
+}
+
+/// 查询用户的过滤器默认值
+fn default_ldap_user_filter() -> String {
+    "(uid={login})".into()
+}
+
+/// 用户名属性默认值
+fn default_ldap_username_attribute() -> String {
+    "uid".into()
+}
+
+/// 邮箱属性默认值
+fn default_ldap_email_attribute() -> String {
+    "mail".into()
+}
+
+/// 昵称属性默认值
+fn default_ldap_name_attribute() -> String {
+    "cn".into()
+}
+
+impl LdapConfig {
+    /// 获取超时时间
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout.unwrap_or_else(|| Duration::from_secs(5))
+    }
+}
+
+/// 密码策略配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct PasswordPolicyConfig {
+    /// 最小长度 (按字符计算)
+    #[serde(
+        default = "default_password_min_length",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub min_length: usize,
+    /// 最大长度, 避免过长的密码占用哈希计算资源
+    #[serde(
+        default = "default_password_max_length",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub max_length: usize,
+    /// 是否要求包含小写字母
+    #[serde(default)]
+    pub require_lowercase: bool,
+    /// 是否要求包含大写字母
+    #[serde(default)]
+    pub require_uppercase: bool,
+    /// 是否要求包含数字
+    #[serde(default)]
+    pub require_digit: bool,
+    /// 是否要求包含特殊字符
+    #[serde(default)]
+    pub require_symbol: bool,
+    /// 估算熵的最小值 (位), 为 0 时不检查
+    #[serde(default)]
+    pub min_entropy: f64,
+    /// 禁止包含的单词 (不区分大小写), 用户名和邮箱总是禁止包含
+    #[serde(default)]
+    pub banned_words: Vec<String>,
+    /// 已泄露密码检查
+    #[serde(default)]
+    pub breached: BreachedPasswordsConfig,
+}
+
+/// 已泄露密码检查配置
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct BreachedPasswordsConfig {
+    /// 已泄露密码列表 (Have I Been Pwned 下载格式) 的路径, 可以是单个文件或按前缀分文件的目录, 为空时不检查
+    pub path: Option<String>,
+    /// 泄露次数少于该值的密码不处理
+    #[serde(default, deserialize_with = "deserialize_number_from_string")]
+    pub min_count: u64,
+    /// 密码已泄露时的处理方式
+    #[serde(default)]
+    pub mode: BreachedPasswordMode,
+}
+
+/// 密码已泄露时的处理方式
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BreachedPasswordMode {
+    /// 只记录警告日志
+    Warn,
+    /// 拒绝使用
+    #[default]
+    Reject,
+}
+
+impl BreachedPasswordsConfig {
+    /// 加载已泄露密码列表
+    pub fn get_breached_passwords(&self) -> anyhow::Result<BreachedPasswords> {
+        let breached = match &self.path {
+            Some(path) => BreachedPasswords::load(path, self.min_count)
+                .with_context(|| format!("加载已泄露密码列表: [{}] 失败", path))?,
+            None => BreachedPasswords::Disabled,
+        };
+        log::info!(
+            "初始化 '已泄露密码列表: [{}] [{:?}]' 完成!",
+            breached,
+            self.mode
+        );
+        Ok(breached)
+    }
+}
+
+/// 密码最小长度默认值
+fn default_password_min_length() -> usize {
+    8
+}
+
+/// 密码最大长度默认值
+fn default_password_max_length() -> usize {
+    128
+}
+
+/// 登录失败限制配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct ThrottleConfig {
+    /// 同一账号允许连续失败的次数, 超过后锁定
+    #[serde(
+        default = "default_max_account_failures",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub max_account_failures: i32,
+    /// 同一 IP 允许连续失败的次数, 超过后锁定
+    #[serde(
+        default = "default_max_ip_failures",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub max_ip_failures: i32,
+    /// 首次锁定时长, 之后每次失败翻倍
+    #[serde(with = "humantime_serde", default)]
+    pub lockout: Option<Duration>,
+    /// 最长锁定时长
+    #[serde(with = "humantime_serde", default)]
+    pub max_lockout: Option<Duration>,
+    /// 最后一次失败超过该时长后重新计数
+    #[serde(with = "humantime_serde", default)]
+    pub window: Option<Duration>,
+}
+
+impl ThrottleConfig {
+    /// 获取首次锁定时长
+    pub fn get_lockout(&self) -> chrono::Duration {
+        let lockout = self.lockout.unwrap_or_else(|| Duration::from_secs(30));
+        chrono::Duration::from_std(lockout).unwrap()
+    }
+
+    /// 获取最长锁定时长
+    pub fn get_max_lockout(&self) -> chrono::Duration {
+        let max_lockout = self
+            .max_lockout
+            .unwrap_or_else(|| Duration::from_secs(60 * 60));
+        chrono::Duration::from_std(max_lockout).unwrap()
+    }
+
+    /// 获取重新计数的时长
+    pub fn get_window(&self) -> chrono::Duration {
+        let window = self.window.unwrap_or_else(|| Duration::from_secs(60 * 60));
+        chrono::Duration::from_std(window).unwrap()
+    }
+}
+
+/// 同一账号允许连续失败的次数默认值
+fn default_max_account_failures() -> i32 {
+    5
+}
+
+/// 同一 IP 允许连续失败的次数默认值
+fn default_max_ip_failures() -> i32 {
+    20
+}
+
+impl AuthConfig {
+    /// 获取重置密码令牌过期时间
+    pub fn get_password_reset_expires(&self) -> chrono::Duration {
+        let expires = self
+            .password_reset_expires
+            .unwrap_or_else(|| Duration::from_secs(30 * 60));
+        chrono::Duration::from_std(expires).unwrap()
+    }
+
+    /// 获取验证密码后可以执行敏感操作的时长
+    pub fn get_reauthentication_window(&self) -> chrono::Duration {
+        let window = self
+            .reauthentication_window
+            .unwrap_or_else(|| Duration::from_secs(10 * 60));
+        chrono::Duration::from_std(window).unwrap()
+    }
+
+    /// 获取用户名密码登录的身份验证方式
+    pub fn get_sign_in_providers(&self) -> anyhow::Result<AuthProviders> {
+        let providers = AuthProviders::new(&self.sign_in_providers, &self.ldap)?;
+        log::info!("初始化 '身份验证方式: {:?}' 完成!", &self.sign_in_providers);
+        Ok(providers)
+    }
+
+    /// 获取邮箱验证令牌过期时间
+    pub fn get_email_verification_expires(&self) -> chrono::Duration {
+        let expires = self
+            .email_verification_expires
+            .unwrap_or_else(|| Duration::from_secs(24 * 60 * 60));
+        chrono::Duration::from_std(expires).unwrap()
+    }
+}
+
+/// 查询用户名是否存在的接口配置, 该接口可以用来枚举已注册的用户
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ExistenceCheckConfig {
+    /// 是否开启
+    #[serde(default)]
+    pub enabled: bool,
+    /// 同一 IP 在计数周期 (`auth.throttle.window`) 内允许查询的次数, 超过后按登录失败限制锁定
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_requests: Option<i32>,
+}
+
+impl ExistenceCheckConfig {
+    /// 获取同一 IP 允许查询的次数
+    pub fn get_max_requests(&self) -> i32 {
+        self.max_requests.unwrap_or(20)
+    }
+}
+
+/// OpenID Connect 登录配置
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct OidcConfig {
+    /// 登录状态 (state) 过期时间, 超过后需要重新发起登录
+    #[serde(with = "humantime_serde", default)]
+    pub state_expires: Option<Duration>,
+    /// 身份提供方
+    #[serde(default)]
+    pub providers: Vec<OidcProviderConfig>,
+}
+
+/// OpenID Connect 身份提供方配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct OidcProviderConfig {
+    /// 名称, 登录时用于选择身份提供方
+    pub name: String,
+    /// 签发人, 通过 `{issuer}/.well-known/openid-configuration` 获取端点
+    pub issuer: String,
+    pub client_id: String,
+    /// 客户端秘钥, 公共客户端不需要
+    pub client_secret: Option<String>,
+    /// 回调地址, 需要在身份提供方注册
+    pub redirect_uri: String,
+    /// 申请的 scope
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// 第三方账号首次登录时是否自动创建用户
+    #[serde(default)]
+    pub auto_provision: bool,
+    /// 是否按 (身份提供方已验证的) 邮箱绑定已有用户
+    #[serde(default)]
+    pub link_by_email: bool,
+}
+
+/// OpenID Connect 申请的 scope 默认值
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".into(), "email".into(), "profile".into()]
+}
+
+impl OidcConfig {
+    /// 获取登录状态过期时间
+    pub fn get_state_expires(&self) -> chrono::Duration {
+        let expires = self
+            .state_expires
+            .unwrap_or_else(|| Duration::from_secs(10 * 60));
+        chrono::Duration::from_std(expires).unwrap()
+    }
+
+    /// 获取身份提供方
+    pub fn get_providers(&self) -> anyhow::Result<OidcProviders> {
+        let providers = OidcProviders::new(&self.providers)?;
+        log::info!(
+            "初始化 'OpenID Connect 身份提供方: {:?}' 完成!",
+            providers.names()
+        );
+        Ok(providers)
+    }
+}
+
+/// 邮件相关配置
+#[derive(Deserialize, Clone, Debug)]
+pub struct MailConfig {
+    /// 发送器
+    pub sender: MailSenderType,
+    /// 发件人
+    pub from: String,
+    /// 邮件目录, 发送器为 file 时使用
+    pub dir: Option<String>,
+}
+
+/// 邮件发送器类型
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MailSenderType {
+    /// 写入文件
+    File,
+    /// 保存在内存中
+    Memory,
+}
+
+impl MailConfig {
+    /// 获取邮件发送器
+    pub fn get_mailer(&self) -> anyhow::Result<Arc<dyn MailSender>> {
+        let mailer: Arc<dyn MailSender> = match self.sender {
+            MailSenderType::File => Arc::new(FileMailSender {
+                from: self.from.clone(),
+                dir: PathBuf::from(self.dir.as_deref().unwrap_or("mail")),
+            }),
+            MailSenderType::Memory => Arc::new(MemoryMailSender::default()),
+        };
+        log::info!("初始化 '邮件发送器: [{:?}]' 完成!", self.sender);
+        Ok(mailer)
     }
 }
 
@@ -230,3 +1088,147 @@ fn get_config_dir() -> anyhow::Result<PathBuf> {
     };
     Ok(config_dir)
 }
+
+#[test]
+fn test_check_secret() {
+    assert_eq!(check_secret(None, 0), Some("未配置".to_string()));
+    assert_eq!(check_secret(Some(" "), 0), Some("未配置".to_string()));
+    assert_eq!(
+        check_secret(Some("Your-256-Bit-Secret"), MIN_SECRET_LENGTH),
+        Some("仍在使用示例配置中的值".to_string())
+    );
+    assert_eq!(
+        check_secret(Some("too-short"), MIN_SECRET_LENGTH),
+        Some(format!("长度不能少于 {} 个字符", MIN_SECRET_LENGTH))
+    );
+    assert_eq!(check_secret(Some("too-short"), 0), None);
+    assert_eq!(
+        check_secret(Some(&"k".repeat(MIN_SECRET_LENGTH)), MIN_SECRET_LENGTH),
+        None
+    );
+}
+
+#[test]
+fn test_resolve_secret_files() {
+    let dir = std::env::temp_dir().join(format!("secrets-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("jwt"), "jwt-secret-from-file\n").unwrap();
+    std::fs::write(dir.join("db"), "db-password-from-file").unwrap();
+
+    let mut settings = config::Config::new();
+    settings
+        .merge(config::File::from_str(
+            r#"
+            [jwt]
+            secret = "your-256-bit-secret"
+            # 相对路径基于配置文件目录
+            secret_file = "jwt"
+            issuer = "Server"
+
+            [database]
+            password = "123456"
+            "#,
+            config::FileFormat::Toml,
+        ))
+        .unwrap();
+    // 环境变量 SERVER_DATABASE_PASSWORD_FILE
+    let key = secret_file_key("SERVER_DATABASE_PASSWORD_FILE").unwrap();
+    settings
+        .set(&key, dir.join("db").to_str().unwrap())
+        .unwrap();
+
+    let value = resolve_secret_files(settings.try_into().unwrap(), &dir).unwrap();
+    let resolved: HashMap<String, HashMap<String, String>> = value.try_into().unwrap();
+    assert_eq!(resolved["jwt"]["secret"], "jwt-secret-from-file");
+    assert_eq!(resolved["jwt"]["issuer"], "Server");
+    assert!(!resolved["jwt"].contains_key("secret_file"));
+    assert_eq!(resolved["database"]["password"], "db-password-from-file");
+
+    // 秘钥文件不存在
+    let mut settings = config::Config::new();
+    settings.set("hash.secret_file", "missing").unwrap();
+    assert!(resolve_secret_files(settings.try_into().unwrap(), &dir).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_secret_file_key() {
+    assert_eq!(
+        secret_file_key("SERVER_DATABASE_PASSWORD_FILE").unwrap(),
+        "database.password_file"
+    );
+    assert_eq!(
+        secret_file_key("SERVER_CRYPTO_JWT_SECRET_FILE").unwrap(),
+        "crypto.jwt.secret_file"
+    );
+    assert_eq!(
+        secret_file_key("SERVER_CRYPTO_MFA_ENCRYPTION_KEY_FILE").unwrap(),
+        "crypto.mfa.encryption_key_file"
+    );
+    assert_eq!(
+        secret_file_key("SERVER_AUTH_OIDC_PROVIDERS_0_CLIENT_SECRET_FILE").unwrap(),
+        "auth.oidc.providers[0].client_secret_file"
+    );
+    assert_eq!(
+        secret_file_key("SERVER_AUTH_LDAP_1_BIND_PASSWORD_FILE").unwrap(),
+        "auth.ldap[1].bind_password_file"
+    );
+    assert_eq!(
+        secret_file_key("SERVER_CRYPTO_JWT_KEYS_0_SECRET_FILE").unwrap(),
+        "crypto.jwt.keys[0].secret_file"
+    );
+    // 不是秘钥配置项或秘钥文件
+    assert!(secret_file_key("SERVER_LOG_FILE").is_none());
+    assert!(secret_file_key("SERVER_CRYPTO_JWT_SECRET").is_none());
+    assert!(secret_file_key("OTHER_CRYPTO_JWT_SECRET_FILE").is_none());
+
+    let dir = std::env::temp_dir().join(format!("secrets-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("mfa"), "mfa-key-from-file\n").unwrap();
+    std::fs::write(dir.join("oidc"), "oidc-secret-from-file\n").unwrap();
+
+    let mut settings = config::Config::new();
+    settings
+        .merge(config::File::from_str(
+            r#"
+            [crypto.mfa]
+            encryption_key = "your-256-bit-secret"
+
+            [[auth.oidc.providers]]
+            name = "corp"
+            client_secret = "secret"
+
+            [[auth.oidc.providers]]
+            name = "other"
+            client_secret = "other-secret"
+            "#,
+            config::FileFormat::Toml,
+        ))
+        .unwrap();
+    for (name, path) in [
+        ("SERVER_CRYPTO_MFA_ENCRYPTION_KEY_FILE", "mfa"),
+        ("SERVER_AUTH_OIDC_PROVIDERS_0_CLIENT_SECRET_FILE", "oidc"),
+    ]
+    .iter()
+    {
+        settings
+            .set(&secret_file_key(name).unwrap(), *path)
+            .unwrap();
+    }
+
+    let value = resolve_secret_files(settings.try_into().unwrap(), &dir).unwrap();
+    let resolved: serde_json::Value = value.try_into().unwrap();
+    assert_eq!(
+        resolved["crypto"]["mfa"]["encryption_key"],
+        "mfa-key-from-file"
+    );
+    // 数组中的其他配置项和其他元素不受影响
+    let providers = resolved["auth"]["oidc"]["providers"].as_array().unwrap();
+    assert_eq!(providers.len(), 2);
+    assert_eq!(providers[0]["name"], "corp");
+    assert_eq!(providers[0]["client_secret"], "oidc-secret-from-file");
+    assert_eq!(providers[1]["client_secret"], "other-secret");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,251 @@
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::common::error::errors::AppError;
+use crate::security::auth::ClientInfo;
+
+/// 审计日志模型
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex)]
+pub struct AuditEvents {
+    pub id: Uuid,
+    /// 事件类型
+    pub action: String,
+    /// 结果: success / failure
+    pub outcome: String,
+    /// 操作人id, 未登录时为空
+    pub actor_id: Option<Uuid>,
+    /// 被操作的用户id
+    pub subject_id: Option<Uuid>,
+    /// 登录失败时提交的登录名
+    pub login: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    #[graphql(skip)]
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl AuditEvents {
+    /// 附加信息
+    async fn metadata(&self) -> Json<serde_json::Value> {
+        Json(self.metadata.clone())
+    }
+}
+
+/// 审计事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    /// 登录 (用户名密码 / 第三方登录)
+    SignIn,
+    /// 登录时提交两步验证码
+    MfaVerify,
+    /// 使用刷新令牌换取新的令牌
+    TokenRefresh,
+    /// 重新验证密码
+    Reauthenticate,
+    /// 注册
+    Register,
+    /// 验证邮箱
+    EmailVerify,
+    /// 重新发送验证邮件
+    EmailVerificationResend,
+    /// 退出登录
+    SignOut,
+    /// 退出所有设备
+    SignOutEverywhere,
+    /// 注销会话
+    SessionRevoke,
+    /// 修改密码
+    PasswordChange,
+    /// 发送重置密码邮件
+    PasswordResetRequest,
+    /// 重置密码
+    PasswordReset,
+    /// 启用两步验证
+    MfaEnable,
+    /// 关闭两步验证
+    MfaDisable,
+    /// 重新生成恢复码
+    RecoveryCodesRegenerate,
+    /// 创建个人访问令牌
+    AccessTokenCreate,
+    /// 注销个人访问令牌
+    AccessTokenRevoke,
+    /// 分配角色
+    RoleAssign,
+    /// 移除角色
+    RoleRemove,
+    /// 解除登录锁定
+    AccountUnlock,
+    /// 管理员代登录
+    Impersonate,
+    /// 代登录期间的请求
+    ImpersonatedRequest,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::SignIn => "sign_in",
+            AuditAction::MfaVerify => "mfa_verify",
+            AuditAction::TokenRefresh => "token_refresh",
+            AuditAction::Reauthenticate => "reauthenticate",
+            AuditAction::Register => "register",
+            AuditAction::EmailVerify => "email_verify",
+            AuditAction::EmailVerificationResend => "email_verification_resend",
+            AuditAction::SignOut => "sign_out",
+            AuditAction::SignOutEverywhere => "sign_out_everywhere",
+            AuditAction::SessionRevoke => "session_revoke",
+            AuditAction::PasswordChange => "password_change",
+            AuditAction::PasswordResetRequest => "password_reset_request",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::MfaEnable => "mfa_enable",
+            AuditAction::MfaDisable => "mfa_disable",
+            AuditAction::RecoveryCodesRegenerate => "recovery_codes_regenerate",
+            AuditAction::AccessTokenCreate => "access_token_create",
+            AuditAction::AccessTokenRevoke => "access_token_revoke",
+            AuditAction::RoleAssign => "role_assign",
+            AuditAction::RoleRemove => "role_remove",
+            AuditAction::AccountUnlock => "account_unlock",
+            AuditAction::Impersonate => "impersonate",
+            AuditAction::ImpersonatedRequest => "impersonated_request",
+        }
+    }
+}
+
+/// 审计事件结果
+pub const OUTCOME_SUCCESS: &str = "success";
+pub const OUTCOME_FAILURE: &str = "failure";
+
+/// 新的审计事件
+///
+/// ```text
+/// NewAuditEvent::new(AuditAction::PasswordChange, &client)
+///     .actor(&user.id)
+///     .subject(&user.id)
+///     .result(&result)
+/// ```
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub action: AuditAction,
+    pub outcome: &'static str,
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub login: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+impl NewAuditEvent {
+    /// 默认结果为成功
+    pub fn new(action: AuditAction, client: &ClientInfo) -> NewAuditEvent {
+        NewAuditEvent {
+            action,
+            outcome: OUTCOME_SUCCESS,
+            actor_id: None,
+            subject_id: None,
+            login: None,
+            ip: client.ip.map(|ip| ip.to_string()),
+            user_agent: client.user_agent.clone(),
+            metadata: serde_json::Map::new(),
+        }
+    }
+
+    /// 操作人
+    pub fn actor(mut self, actor_id: &Uuid) -> NewAuditEvent {
+        self.actor_id = Some(*actor_id);
+        self
+    }
+
+    /// 被操作的用户
+    pub fn subject(mut self, subject_id: &Uuid) -> NewAuditEvent {
+        self.subject_id = Some(*subject_id);
+        self
+    }
+
+    /// 提交的登录名
+    pub fn login(mut self, login: &str) -> NewAuditEvent {
+        self.login = Some(login.to_string());
+        self
+    }
+
+    /// 附加信息
+    pub fn metadata(mut self, key: &str, value: impl Into<serde_json::Value>) -> NewAuditEvent {
+        self.metadata.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// 按业务结果设置事件结果, 失败时在附加信息中记录原因, 非业务错误只记录为服务器内部错误
+    pub fn result<T>(mut self, result: &anyhow::Result<T>) -> NewAuditEvent {
+        if let Err(error) = result {
+            let reason = match error.downcast_ref::<AppError>() {
+                Some(error) => error.to_string(),
+                None => AppError::InternalError.to_string(),
+            };
+            self.outcome = OUTCOME_FAILURE;
+            self = self.metadata("error", reason);
+        }
+        self
+    }
+}
+
+/// 审计日志查询条件
+#[derive(InputObject, Default)]
+pub struct AuditEventFilter {
+    /// 事件类型, 例如 sign_in
+    pub action: Option<String>,
+    /// 结果: success / failure
+    pub outcome: Option<String>,
+    /// 操作人id
+    pub actor_id: Option<Uuid>,
+    /// 被操作的用户id
+    pub subject_id: Option<Uuid>,
+    /// 客户端 IP
+    pub ip: Option<String>,
+    /// 开始时间 (包含)
+    pub from: Option<DateTime<Utc>>,
+    /// 结束时间 (不包含)
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// 分页参数
+#[derive(InputObject, Deserialize, Validate)]
+pub struct PageVM {
+    /// 页码, 从 1 开始
+    #[validate(range(min = 1, message = "页码不符合要求"))]
+    #[graphql(default = 1)]
+    pub page: i64,
+    /// 每页数量
+    #[validate(range(min = 1, max = 100, message = "每页数量不符合要求"))]
+    #[graphql(default = 20)]
+    pub size: i64,
+}
+
+impl Default for PageVM {
+    fn default() -> Self {
+        PageVM { page: 1, size: 20 }
+    }
+}
+
+impl PageVM {
+    pub fn offset(&self) -> i64 {
+        (self.page - 1).saturating_mul(self.size)
+    }
+}
+
+/// 审计日志分页结果
+#[derive(SimpleObject)]
+pub struct AuditEventPage {
+    pub items: Vec<AuditEvents>,
+    /// 总数
+    pub total: i64,
+    pub page: i64,
+    pub size: i64,
+}
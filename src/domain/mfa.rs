@@ -0,0 +1,41 @@
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::domain::users::UsersToken;
+
+/// 两步验证 (TOTP) 模型
+#[derive(FromRow, Debug)]
+pub struct UserTotp {
+    pub user_id: Uuid,
+    /// 加密后的秘钥
+    pub secret: String,
+    pub last_used_step: Option<i64>,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 绑定两步验证返回的秘钥, 用户在身份验证器 App 中添加后提交验证码确认
+#[derive(SimpleObject)]
+pub struct TotpEnrollment {
+    /// base32 编码的秘钥, 用于手动输入
+    pub secret: String,
+    /// otpauth URI, 用于生成二维码
+    pub otpauth_uri: String,
+}
+
+/// 密码验证通过, 还需要提交两步验证码
+#[derive(SimpleObject)]
+pub struct MfaChallenge {
+    /// 两步验证令牌, 提交验证码时使用
+    pub mfa_token: String,
+    pub expires: i64,
+}
+
+/// 登录结果: 未启用两步验证时直接返回令牌, 否则返回两步验证令牌
+#[derive(Union)]
+pub enum SignInResult {
+    Token(UsersToken),
+    MfaChallenge(MfaChallenge),
+}
@@ -1 +1,5 @@
+pub mod audit;
+pub mod mfa;
+pub mod oidc;
+pub mod tokens;
 pub mod users;
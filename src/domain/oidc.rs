@@ -0,0 +1,27 @@
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// 第三方账号模型
+#[derive(FromRow, Debug)]
+pub struct ExternalIdentities {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// 身份提供方名称
+    pub provider: String,
+    /// 用户在身份提供方的唯一标识
+    pub subject: String,
+    pub email: Option<String>,
+    pub last_login_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 发起第三方登录, 客户端跳转到 `url`, 回调时提交授权码和 `state`
+#[derive(SimpleObject)]
+pub struct OidcAuthorization {
+    /// 身份提供方的授权地址
+    pub url: String,
+    /// 登录状态, 客户端需要保存并与回调中的 state 比较
+    pub state: String,
+}
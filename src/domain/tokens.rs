@@ -0,0 +1,129 @@
+use async_graphql::*;
+use chrono::{DateTime, Local, Utc};
+use serde::Deserialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// 刷新令牌族模型
+#[derive(FromRow, Debug)]
+pub struct TokenFamilies {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub last_used_at: DateTime<Utc>,
+}
+
+/// 登录会话, 对应一个未注销的令牌族
+#[derive(SimpleObject)]
+pub struct Sessions {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// 是否为发起本次请求的会话
+    pub current: bool,
+    pub created_at: DateTime<Local>,
+    pub last_used_at: DateTime<Local>,
+}
+
+impl Sessions {
+    pub fn new(family: TokenFamilies, current_sid: &Uuid) -> Sessions {
+        Sessions {
+            current: family.id == *current_sid,
+            id: family.id,
+            user_agent: family.user_agent,
+            ip: family.ip,
+            created_at: family.created_at.with_timezone(&Local),
+            last_used_at: family.last_used_at.with_timezone(&Local),
+        }
+    }
+}
+
+/// 刷新令牌模型
+#[derive(FromRow, Debug)]
+pub struct RefreshTokens {
+    pub id: Uuid,
+    pub family_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 已注销令牌模型
+#[derive(FromRow, Debug)]
+pub struct RevokedTokens {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 重置密码令牌模型
+#[derive(FromRow, Debug)]
+pub struct PasswordResetTokens {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 邮箱验证令牌模型
+#[derive(FromRow, Debug)]
+pub struct EmailVerificationTokens {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 登录失败记录模型
+#[derive(FromRow, Debug)]
+pub struct LoginThrottles {
+    pub key: String,
+    pub failures: i32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// 个人访问令牌模型
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+pub struct PersonalAccessTokens {
+    pub id: Uuid,
+    #[graphql(skip)]
+    pub user_id: Uuid,
+    pub name: String,
+    #[graphql(skip)]
+    pub token_hash: String,
+    /// 权限范围
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 创建个人访问令牌
+#[derive(Deserialize, InputObject, Validate)]
+pub struct NewAccessToken {
+    #[validate(length(min = 1, max = 100, message = "令牌名称不符合要求"))]
+    pub name: String,
+    /// 权限范围, 只能是当前用户拥有的权限
+    pub scopes: Vec<String>,
+    /// 过期时间, 不填表示永不过期
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 新创建的个人访问令牌, 令牌明文只会返回这一次
+#[derive(SimpleObject)]
+pub struct CreatedAccessToken {
+    pub token: String,
+    pub access_token: PersonalAccessTokens,
+}
@@ -9,7 +9,7 @@ use uuid::Uuid;
 use validator::Validate;
 
 /// 用户模型
-#[derive(SimpleObject, FromRow, Deserialize, Serialize)]
+#[derive(SimpleObject, FromRow, Deserialize, Serialize, Clone, Debug)]
 #[graphql(complex)]
 pub struct Users {
     pub id: Uuid,
@@ -47,7 +47,7 @@ pub struct NewUser {
     pub username: String,
     #[validate(email(message = "邮箱不符合"))]
     pub email: String,
-    #[validate(length(min = 6, message = "密码不符合"))]
+    /// 按配置的密码策略检查
     pub password: String,
     #[validate(length(min = 3, message = "昵称不符合"))]
     pub nickname: String,
@@ -76,3 +76,12 @@ pub struct UsersToken {
     pub refash_token: String,
     pub expires: i64,
 }
+
+/// 代登录令牌, 只有访问令牌, 过期后需要重新代登录
+#[derive(SimpleObject)]
+pub struct ImpersonationToken {
+    pub access_token: String,
+    pub expires: i64,
+    /// 被代登录的用户
+    pub user: Users,
+}
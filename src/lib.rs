@@ -1,10 +1,15 @@
 use std::{future::Future, sync::Arc};
 
-use crate::{common::error::errors, config::configs::{Configs, CryptoConfig, DatabaseConfig, LogConfig}};
+use crate::{common::error::errors, config::configs::{Configs, CryptoConfig, DatabaseConfig, LogConfig, MailConfig}};
 
 
+use common::mail::mailer::MailSender;
 use regex::Regex;
+use security::breach::BreachedPasswords;
 use security::crypto::CryptoService;
+use security::oidc::OidcProviders;
+use security::provider::AuthProviders;
+use security::revocation::RevocationStore;
 use sqlx::{Pool, Postgres};
 use warp::{Filter};
 
@@ -24,7 +29,22 @@ lazy_static::lazy_static! {
     static ref POOL: Pool<Postgres> = DatabaseConfig::init(&CONFIGS.database).unwrap();
 
     // 加密工具
-    static ref CRYPTO: Arc<CryptoService> = CryptoConfig::get_crypto_server(&CONFIGS.crypto);
+    static ref CRYPTO: Arc<CryptoService> = CryptoConfig::get_crypto_server(&CONFIGS.crypto).unwrap();
+
+    // 邮件发送器
+    static ref MAILER: Arc<dyn MailSender> = MailConfig::get_mailer(&CONFIGS.mail).unwrap();
+
+    // OpenID Connect 身份提供方
+    static ref OIDC: OidcProviders = CONFIGS.auth.oidc.get_providers().unwrap();
+
+    // 用户名密码登录的身份验证方式
+    static ref AUTH_PROVIDERS: AuthProviders = CONFIGS.auth.get_sign_in_providers().unwrap();
+
+    // 已泄露密码列表
+    static ref BREACHED_PASSWORDS: BreachedPasswords = CONFIGS.auth.password_policy.breached.get_breached_passwords().unwrap();
+
+    // 已注销令牌
+    static ref REVOCATIONS: RevocationStore = RevocationStore::default();
 
     // 正则
     static ref EMAIL_REGEX: Regex = Regex::new(r"(@)").unwrap();
@@ -49,17 +69,31 @@ impl Application {
         // 取下链接测试下
         POOL.acquire().await.expect("获取数据库连接失败");
 
+        lazy_static::initialize(&CRYPTO);
+        lazy_static::initialize(&MAILER);
+        lazy_static::initialize(&OIDC);
+        lazy_static::initialize(&AUTH_PROVIDERS);
+        lazy_static::initialize(&BREACHED_PASSWORDS);
+
+        // 加载已注销令牌并定时清理
+        REVOCATIONS.load().await.expect("加载已注销令牌失败");
+        REVOCATIONS.spawn_purge(CONFIGS.crypto.jwt.get_purge_interval());
+
         // graphql 入口
         let graphql = web::gql::graphql(CONFIGS.clone());
 
         // playground 入口
         let playground = web::gql::graphiql(CONFIGS.clone());
 
+        // jwt 公钥入口
+        let jwks = web::jwks::jwks();
+
         let routes = playground
             // graphql 入口
             .or(graphql)
+            .or(jwks)
             // 错误处理
-            .recover(|err| errors::recover(err));
+            .recover(errors::recover);
 
         let addr = CONFIGS.server.get_address();
         let serve = warp::serve(routes).run(addr);
@@ -67,3 +101,15 @@ impl Application {
         Ok(serve)
     }
 }
+
+/// 在共用的运行时中执行使用数据库的测试
+///
+/// 连接池中的连接绑定在创建它的运行时上, `#[tokio::test]` 每个测试使用独立的运行时,
+/// 测试结束后留在连接池中的连接随之失效, 其他测试取到后会一直等待直到获取连接超时
+#[cfg(test)]
+pub fn block_on_db<F: Future>(future: F) -> F::Output {
+    lazy_static::lazy_static! {
+        static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Runtime::new().unwrap();
+    }
+    RUNTIME.block_on(future)
+}
@@ -0,0 +1,105 @@
+use anyhow::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{domain::tokens::PersonalAccessTokens, POOL};
+
+pub struct AccessTokensRepository;
+
+#[async_trait]
+pub trait ExtAccessTokensRepository {
+    /// 保存个人访问令牌
+    async fn create(
+        user_id: &Uuid,
+        name: &str,
+        token_hash: &str,
+        scopes: &[String],
+        expires_at: Option<&DateTime<Utc>>,
+    ) -> Result<PersonalAccessTokens>;
+
+    /// 根据令牌哈希查询有效的个人访问令牌, 同时更新最后使用时间
+    async fn use_token(token_hash: &str) -> Result<Option<PersonalAccessTokens>>;
+
+    /// 查询用户未注销的个人访问令牌
+    async fn find_by_user(user_id: &Uuid) -> Result<Vec<PersonalAccessTokens>>;
+
+    /// 注销属于指定用户的个人访问令牌
+    async fn revoke(id: &Uuid, user_id: &Uuid) -> Result<bool>;
+}
+
+#[async_trait]
+impl ExtAccessTokensRepository for AccessTokensRepository {
+    async fn create(
+        user_id: &Uuid,
+        name: &str,
+        token_hash: &str,
+        scopes: &[String],
+        expires_at: Option<&DateTime<Utc>>,
+    ) -> Result<PersonalAccessTokens> {
+        let row = sqlx::query_as!(
+            PersonalAccessTokens,
+            //language=sql
+            r#"INSERT INTO personal_access_tokens(user_id, name, token_hash, scopes, expires_at)
+               VALUES ($1, $2, $3, $4, $5)
+               RETURNING *"#,
+            user_id,
+            name,
+            token_hash,
+            scopes,
+            expires_at
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("保存个人访问令牌")?;
+
+        Ok(row)
+    }
+
+    async fn use_token(token_hash: &str) -> Result<Option<PersonalAccessTokens>> {
+        let row = sqlx::query_as!(
+            PersonalAccessTokens,
+            //language=sql
+            r#"UPDATE personal_access_tokens
+               SET last_used_at = current_timestamp
+               WHERE token_hash = $1
+                 AND revoked_at IS NULL
+                 AND (expires_at IS NULL OR expires_at > current_timestamp)
+               RETURNING *"#,
+            token_hash
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("使用个人访问令牌")?;
+
+        Ok(row)
+    }
+
+    async fn find_by_user(user_id: &Uuid) -> Result<Vec<PersonalAccessTokens>> {
+        let rows = sqlx::query_as!(
+            PersonalAccessTokens,
+            //language=sql
+            "SELECT * FROM personal_access_tokens WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at DESC",
+            user_id
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询用户个人访问令牌")?;
+
+        Ok(rows)
+    }
+
+    async fn revoke(id: &Uuid, user_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            "UPDATE personal_access_tokens SET revoked_at = current_timestamp WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            id,
+            user_id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("注销个人访问令牌")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
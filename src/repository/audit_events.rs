@@ -0,0 +1,110 @@
+use anyhow::*;
+use async_trait::async_trait;
+
+use crate::domain::audit::{AuditEventFilter, AuditEvents, NewAuditEvent};
+use crate::POOL;
+
+pub struct AuditEventsRepository;
+
+#[async_trait]
+pub trait ExtAuditEventsRepository {
+    /// 保存审计事件
+    async fn create(event: &NewAuditEvent) -> Result<()>;
+
+    /// 按条件分页查询审计事件, 按时间倒序
+    async fn find_page(
+        filter: &AuditEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEvents>>;
+
+    /// 按条件统计审计事件数量
+    async fn count(filter: &AuditEventFilter) -> Result<i64>;
+}
+
+#[async_trait]
+impl ExtAuditEventsRepository for AuditEventsRepository {
+    async fn create(event: &NewAuditEvent) -> Result<()> {
+        sqlx::query!(
+            //language=sql
+            r#"INSERT INTO audit_events(action, outcome, actor_id, subject_id, login, ip, user_agent, metadata)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+            event.action.as_str(),
+            event.outcome,
+            event.actor_id,
+            event.subject_id,
+            event.login,
+            event.ip,
+            event.user_agent,
+            serde_json::Value::Object(event.metadata.clone())
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("保存审计事件")?;
+
+        Ok(())
+    }
+
+    async fn find_page(
+        filter: &AuditEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEvents>> {
+        let rows = sqlx::query_as!(
+            AuditEvents,
+            //language=sql
+            r#"SELECT *
+               FROM audit_events
+               WHERE ($1::varchar IS NULL OR action = $1)
+                 AND ($2::varchar IS NULL OR outcome = $2)
+                 AND ($3::uuid IS NULL OR actor_id = $3)
+                 AND ($4::uuid IS NULL OR subject_id = $4)
+                 AND ($5::varchar IS NULL OR ip = $5)
+                 AND ($6::timestamptz IS NULL OR created_at >= $6)
+                 AND ($7::timestamptz IS NULL OR created_at < $7)
+               ORDER BY created_at DESC, id
+               LIMIT $8 OFFSET $9"#,
+            filter.action,
+            filter.outcome,
+            filter.actor_id,
+            filter.subject_id,
+            filter.ip,
+            filter.from,
+            filter.to,
+            limit,
+            offset
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询审计事件")?;
+
+        Ok(rows)
+    }
+
+    async fn count(filter: &AuditEventFilter) -> Result<i64> {
+        let row = sqlx::query!(
+            //language=sql
+            r#"SELECT count(*) AS "count!"
+               FROM audit_events
+               WHERE ($1::varchar IS NULL OR action = $1)
+                 AND ($2::varchar IS NULL OR outcome = $2)
+                 AND ($3::uuid IS NULL OR actor_id = $3)
+                 AND ($4::uuid IS NULL OR subject_id = $4)
+                 AND ($5::varchar IS NULL OR ip = $5)
+                 AND ($6::timestamptz IS NULL OR created_at >= $6)
+                 AND ($7::timestamptz IS NULL OR created_at < $7)"#,
+            filter.action,
+            filter.outcome,
+            filter.actor_id,
+            filter.subject_id,
+            filter.ip,
+            filter.from,
+            filter.to
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("统计审计事件")?;
+
+        Ok(row.count)
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{domain::tokens::EmailVerificationTokens, POOL};
+
+pub struct EmailVerificationsRepository;
+
+#[async_trait]
+pub trait ExtEmailVerificationsRepository {
+    /// 保存邮箱验证令牌
+    async fn create(
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &str,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<EmailVerificationTokens>;
+
+    /// 使用邮箱验证令牌并标记用户邮箱已验证, 返回用户id,
+    /// 令牌不存在/已使用/已过期或用户邮箱已变更时返回 None
+    async fn consume(token_hash: &str) -> Result<Option<Uuid>>;
+
+    /// 删除用户未使用的邮箱验证令牌
+    async fn delete_unused_by_user(user_id: &Uuid) -> Result<u64>;
+}
+
+#[async_trait]
+impl ExtEmailVerificationsRepository for EmailVerificationsRepository {
+    async fn create(
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &str,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<EmailVerificationTokens> {
+        let row = sqlx::query_as!(
+            EmailVerificationTokens,
+            //language=sql
+            "INSERT INTO email_verification_tokens(user_id, email, token_hash, expires_at) VALUES ($1, $2, $3, $4) RETURNING *",
+            user_id,
+            email,
+            token_hash,
+            expires_at
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("保存邮箱验证令牌")?;
+
+        Ok(row)
+    }
+
+    async fn consume(token_hash: &str) -> Result<Option<Uuid>> {
+        let mut tx = POOL.begin().await?;
+        let token = sqlx::query!(
+            //language=sql
+            r#"UPDATE email_verification_tokens
+               SET used_at = current_timestamp
+               WHERE token_hash = $1
+                 AND used_at IS NULL
+                 AND expires_at > current_timestamp
+               RETURNING user_id, email"#,
+            token_hash
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .context("使用邮箱验证令牌")?;
+
+        let token = match token {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        let result = sqlx::query!(
+            //language=sql
+            "UPDATE users SET email_verified = true, updated_at = current_timestamp WHERE id = $1 AND email = $2",
+            token.user_id,
+            token.email
+        )
+        .execute(&mut tx)
+        .await
+        .context("标记邮箱已验证")?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        tx.commit().await?;
+
+        Ok(Some(token.user_id))
+    }
+
+    async fn delete_unused_by_user(user_id: &Uuid) -> Result<u64> {
+        let result = sqlx::query!(
+            //language=sql
+            "DELETE FROM email_verification_tokens WHERE user_id = $1 AND used_at IS NULL",
+            user_id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("删除邮箱验证令牌")?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::*;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{domain::oidc::ExternalIdentities, POOL};
+
+pub struct ExternalIdentitiesRepository;
+
+#[async_trait]
+pub trait ExtExternalIdentitiesRepository {
+    /// 根据身份提供方和用户标识查询第三方账号, 同时更新最后登录时间
+    async fn find_for_login(provider: &str, subject: &str) -> Result<Option<ExternalIdentities>>;
+
+    /// 查询用户在指定身份提供方最近登录的账号
+    async fn find_by_user(user_id: &Uuid, provider: &str) -> Result<Option<ExternalIdentities>>;
+
+    /// 绑定第三方账号
+    async fn create(
+        user_id: &Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<ExternalIdentities>;
+}
+
+#[async_trait]
+impl ExtExternalIdentitiesRepository for ExternalIdentitiesRepository {
+    async fn find_for_login(provider: &str, subject: &str) -> Result<Option<ExternalIdentities>> {
+        let row = sqlx::query_as!(
+            ExternalIdentities,
+            //language=sql
+            r#"UPDATE external_identities
+               SET last_login_at = current_timestamp
+               WHERE provider = $1
+                 AND subject = $2
+               RETURNING *"#,
+            provider,
+            subject
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("查询第三方账号")?;
+
+        Ok(row)
+    }
+
+    async fn find_by_user(user_id: &Uuid, provider: &str) -> Result<Option<ExternalIdentities>> {
+        let row = sqlx::query_as!(
+            ExternalIdentities,
+            //language=sql
+            r#"SELECT *
+               FROM external_identities
+               WHERE user_id = $1
+                 AND provider = $2
+               ORDER BY last_login_at DESC
+               LIMIT 1"#,
+            user_id,
+            provider
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("查询用户的第三方账号")?;
+
+        Ok(row)
+    }
+
+    async fn create(
+        user_id: &Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<ExternalIdentities> {
+        let row = sqlx::query_as!(
+            ExternalIdentities,
+            //language=sql
+            "INSERT INTO external_identities(user_id, provider, subject, email) VALUES ($1, $2, $3, $4) RETURNING *",
+            user_id,
+            provider,
+            subject,
+            email
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("绑定第三方账号")?;
+
+        Ok(row)
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::*;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{domain::mfa::UserTotp, POOL};
+
+pub struct MfaRepository;
+
+#[async_trait]
+pub trait ExtMfaRepository {
+    /// 查询用户的两步验证
+    async fn find_totp(user_id: &Uuid) -> Result<Option<UserTotp>>;
+
+    /// 保存未确认的两步验证秘钥, 覆盖之前未确认的秘钥, 已启用时返回 None
+    async fn save_pending_totp(user_id: &Uuid, secret: &str) -> Result<Option<UserTotp>>;
+
+    /// 启用两步验证
+    async fn enable_totp(user_id: &Uuid) -> Result<bool>;
+
+    /// 记录使用的验证码时间步, 时间步不大于上次使用的时间步时返回 false (验证码重放)
+    async fn use_totp_step(user_id: &Uuid, step: i64) -> Result<bool>;
+
+    /// 删除两步验证及恢复码
+    async fn delete_totp(user_id: &Uuid) -> Result<bool>;
+
+    /// 替换用户的恢复码
+    async fn replace_recovery_codes(user_id: &Uuid, code_hashes: &[String]) -> Result<()>;
+
+    /// 使用恢复码, 恢复码不存在或已被使用时返回 false
+    async fn use_recovery_code(user_id: &Uuid, code_hash: &str) -> Result<bool>;
+}
+
+#[async_trait]
+impl ExtMfaRepository for MfaRepository {
+    async fn find_totp(user_id: &Uuid) -> Result<Option<UserTotp>> {
+        let row = sqlx::query_as!(
+            UserTotp,
+            //language=sql
+            "SELECT * FROM user_totp WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("查询两步验证")?;
+
+        Ok(row)
+    }
+
+    async fn save_pending_totp(user_id: &Uuid, secret: &str) -> Result<Option<UserTotp>> {
+        let row = sqlx::query_as!(
+            UserTotp,
+            //language=sql
+            r#"INSERT INTO user_totp(user_id, secret)
+               VALUES ($1, $2)
+               ON CONFLICT (user_id) DO UPDATE
+                   SET secret = excluded.secret, last_used_step = null, created_at = current_timestamp
+                   WHERE user_totp.enabled_at IS NULL
+               RETURNING *"#,
+            user_id,
+            secret
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("保存两步验证秘钥")?;
+
+        Ok(row)
+    }
+
+    async fn enable_totp(user_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            "UPDATE user_totp SET enabled_at = current_timestamp WHERE user_id = $1 AND enabled_at IS NULL",
+            user_id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("启用两步验证")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_totp_step(user_id: &Uuid, step: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            r#"UPDATE user_totp
+               SET last_used_step = $2
+               WHERE user_id = $1
+                 AND (last_used_step IS NULL OR last_used_step < $2)"#,
+            user_id,
+            step
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("记录两步验证码时间步")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_totp(user_id: &Uuid) -> Result<bool> {
+        let mut tx = POOL.begin().await?;
+        sqlx::query!(
+            //language=sql
+            "DELETE FROM recovery_codes WHERE user_id = $1",
+            user_id
+        )
+        .execute(&mut tx)
+        .await
+        .context("删除恢复码")?;
+        let result = sqlx::query!(
+            //language=sql
+            "DELETE FROM user_totp WHERE user_id = $1",
+            user_id
+        )
+        .execute(&mut tx)
+        .await
+        .context("删除两步验证")?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn replace_recovery_codes(user_id: &Uuid, code_hashes: &[String]) -> Result<()> {
+        let mut tx = POOL.begin().await?;
+        sqlx::query!(
+            //language=sql
+            "DELETE FROM recovery_codes WHERE user_id = $1",
+            user_id
+        )
+        .execute(&mut tx)
+        .await
+        .context("删除恢复码")?;
+        sqlx::query!(
+            //language=sql
+            "INSERT INTO recovery_codes(user_id, code_hash) SELECT $1, unnest($2::varchar[])",
+            user_id,
+            code_hashes
+        )
+        .execute(&mut tx)
+        .await
+        .context("保存恢复码")?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn use_recovery_code(user_id: &Uuid, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            r#"UPDATE recovery_codes
+               SET used_at = current_timestamp
+               WHERE user_id = $1
+                 AND code_hash = $2
+                 AND used_at IS NULL"#,
+            user_id,
+            code_hash
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("使用恢复码")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -1 +1,10 @@
+pub mod access_tokens;
+pub mod audit_events;
+pub mod email_verifications;
+pub mod external_identities;
+pub mod mfa;
+pub mod password_resets;
+pub mod roles;
+pub mod throttles;
+pub mod tokens;
 pub mod users;
@@ -0,0 +1,98 @@
+use anyhow::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{domain::tokens::PasswordResetTokens, POOL};
+
+pub struct PasswordResetsRepository;
+
+#[async_trait]
+pub trait ExtPasswordResetsRepository {
+    /// 保存重置密码令牌
+    async fn create(
+        user_id: &Uuid,
+        token_hash: &str,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<PasswordResetTokens>;
+
+    /// 使用重置密码令牌, 返回用户id, 令牌不存在/已使用/已过期时返回 None
+    async fn consume(token_hash: &str) -> Result<Option<Uuid>>;
+
+    /// 查询有效 (未使用且未过期) 的令牌所属用户, 不会使用令牌
+    async fn find_user_id(token_hash: &str) -> Result<Option<Uuid>>;
+
+    /// 删除用户未使用的重置密码令牌
+    async fn delete_unused_by_user(user_id: &Uuid) -> Result<u64>;
+}
+
+#[async_trait]
+impl ExtPasswordResetsRepository for PasswordResetsRepository {
+    async fn create(
+        user_id: &Uuid,
+        token_hash: &str,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<PasswordResetTokens> {
+        let row = sqlx::query_as!(
+            PasswordResetTokens,
+            //language=sql
+            "INSERT INTO password_reset_tokens(user_id, token_hash, expires_at) VALUES ($1, $2, $3) RETURNING *",
+            user_id,
+            token_hash,
+            expires_at
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("保存重置密码令牌")?;
+
+        Ok(row)
+    }
+
+    async fn consume(token_hash: &str) -> Result<Option<Uuid>> {
+        let row = sqlx::query!(
+            //language=sql
+            r#"UPDATE password_reset_tokens
+               SET used_at = current_timestamp
+               WHERE token_hash = $1
+                 AND used_at IS NULL
+                 AND expires_at > current_timestamp
+               RETURNING user_id"#,
+            token_hash
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("使用重置密码令牌")?;
+
+        Ok(row.map(|row| row.user_id))
+    }
+
+    async fn find_user_id(token_hash: &str) -> Result<Option<Uuid>> {
+        let row = sqlx::query!(
+            //language=sql
+            r#"SELECT user_id
+               FROM password_reset_tokens
+               WHERE token_hash = $1
+                 AND used_at IS NULL
+                 AND expires_at > current_timestamp"#,
+            token_hash
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("查询重置密码令牌")?;
+
+        Ok(row.map(|row| row.user_id))
+    }
+
+    async fn delete_unused_by_user(user_id: &Uuid) -> Result<u64> {
+        let result = sqlx::query!(
+            //language=sql
+            "DELETE FROM password_reset_tokens WHERE user_id = $1 AND used_at IS NULL",
+            user_id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("删除重置密码令牌")?;
+
+        Ok(result.rows_affected())
+    }
+}
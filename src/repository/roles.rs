@@ -0,0 +1,100 @@
+use anyhow::*;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::POOL;
+
+pub struct RolesRepository;
+
+#[async_trait]
+pub trait ExtRolesRepository {
+    /// 查询用户的角色名称
+    async fn find_names_by_user(user_id: &Uuid) -> Result<Vec<String>>;
+
+    /// 查询角色拥有的权限名称
+    async fn find_permissions_by_roles(roles: &[String]) -> Result<Vec<String>>;
+
+    /// 检查角色是否存在
+    async fn exists_by_name(name: &str) -> Result<bool>;
+
+    /// 为用户分配角色, 角色不存在或已分配时返回 false
+    async fn assign(user_id: &Uuid, role: &str) -> Result<bool>;
+
+    /// 移除用户的角色
+    async fn remove(user_id: &Uuid, role: &str) -> Result<bool>;
+}
+
+#[async_trait]
+impl ExtRolesRepository for RolesRepository {
+    async fn find_names_by_user(user_id: &Uuid) -> Result<Vec<String>> {
+        let rows = sqlx::query!(
+            //language=sql
+            "SELECT r.name FROM roles r JOIN user_roles ur ON ur.role_id = r.id WHERE ur.user_id = $1 ORDER BY r.name",
+            user_id
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询用户角色")?;
+
+        Ok(rows.into_iter().map(|row| row.name).collect())
+    }
+
+    async fn find_permissions_by_roles(roles: &[String]) -> Result<Vec<String>> {
+        let rows = sqlx::query!(
+            //language=sql
+            r#"SELECT DISTINCT p.name
+               FROM permissions p
+                        JOIN role_permissions rp ON rp.permission_id = p.id
+                        JOIN roles r ON r.id = rp.role_id
+               WHERE r.name = ANY ($1)
+               ORDER BY p.name"#,
+            roles
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询角色权限")?;
+
+        Ok(rows.into_iter().map(|row| row.name).collect())
+    }
+
+    async fn exists_by_name(name: &str) -> Result<bool> {
+        let row = sqlx::query!(
+            //language=sql
+            "SELECT EXISTS(SELECT 1 FROM roles WHERE name = $1)",
+            name
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("检查角色是否存在")?;
+
+        Ok(row.exists.unwrap_or_default())
+    }
+
+    async fn assign(user_id: &Uuid, role: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            "INSERT INTO user_roles(user_id, role_id) SELECT $1, id FROM roles WHERE name = $2 ON CONFLICT DO NOTHING",
+            user_id,
+            role
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("分配用户角色")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove(user_id: &Uuid, role: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            "DELETE FROM user_roles WHERE user_id = $1 AND role_id = (SELECT id FROM roles WHERE name = $2)",
+            user_id,
+            role
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("移除用户角色")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{domain::tokens::LoginThrottles, POOL};
+
+pub struct ThrottlesRepository;
+
+#[async_trait]
+pub trait ExtThrottlesRepository {
+    /// 查询登录失败记录
+    async fn find_by_keys(keys: &[String]) -> Result<Vec<LoginThrottles>>;
+
+    /// 记录一次登录失败, 最后一次失败早于 `reset_before` 时重新计数
+    async fn record_failure(key: &str, reset_before: &DateTime<Utc>) -> Result<LoginThrottles>;
+
+    /// 锁定到指定时间
+    async fn lock(key: &str, locked_until: &DateTime<Utc>) -> Result<()>;
+
+    /// 失败次数减一, 不影响已有的锁定
+    async fn decrement(key: &str) -> Result<()>;
+
+    /// 删除登录失败记录
+    async fn delete(key: &str) -> Result<bool>;
+}
+
+#[async_trait]
+impl ExtThrottlesRepository for ThrottlesRepository {
+    async fn find_by_keys(keys: &[String]) -> Result<Vec<LoginThrottles>> {
+        let rows = sqlx::query_as!(
+            LoginThrottles,
+            //language=sql
+            "SELECT * FROM login_throttles WHERE key = ANY ($1)",
+            keys
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询登录失败记录")?;
+
+        Ok(rows)
+    }
+
+    async fn record_failure(key: &str, reset_before: &DateTime<Utc>) -> Result<LoginThrottles> {
+        let row = sqlx::query_as!(
+            LoginThrottles,
+            //language=sql
+            r#"INSERT INTO login_throttles(key, failures)
+               VALUES ($1, 1)
+               ON CONFLICT (key) DO UPDATE
+                   SET failures        = CASE
+                                             WHEN login_throttles.last_failure_at < $2 THEN 1
+                                             ELSE login_throttles.failures + 1 END,
+                       last_failure_at = current_timestamp
+               RETURNING *"#,
+            key,
+            reset_before
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("记录登录失败")?;
+
+        Ok(row)
+    }
+
+    async fn lock(key: &str, locked_until: &DateTime<Utc>) -> Result<()> {
+        sqlx::query!(
+            //language=sql
+            "UPDATE login_throttles SET locked_until = $2 WHERE key = $1",
+            key,
+            locked_until
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("锁定登录")?;
+
+        Ok(())
+    }
+
+    async fn decrement(key: &str) -> Result<()> {
+        sqlx::query!(
+            //language=sql
+            "UPDATE login_throttles SET failures = failures - 1 WHERE key = $1 AND failures > 0",
+            key
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("减少登录失败次数")?;
+
+        Ok(())
+    }
+
+    async fn delete(key: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            "DELETE FROM login_throttles WHERE key = $1",
+            key
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("删除登录失败记录")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
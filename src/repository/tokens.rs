@@ -0,0 +1,273 @@
+use anyhow::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    domain::tokens::{RefreshTokens, RevokedTokens, TokenFamilies},
+    POOL,
+};
+
+pub struct TokensRepository;
+
+#[async_trait]
+pub trait ExtTokensRepository {
+    /// 创建令牌族, 同时记录登录设备信息
+    async fn create_family(
+        id: &Uuid,
+        user_id: &Uuid,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<TokenFamilies>;
+
+    /// 更新令牌族的最后使用时间和设备信息
+    async fn touch_family(id: &Uuid, user_agent: Option<&str>, ip: Option<&str>) -> Result<()>;
+
+    /// 查询用户的有效会话: 令牌族未注销且还有未使用/未过期的刷新令牌
+    async fn find_active_families_by_user(user_id: &Uuid) -> Result<Vec<TokenFamilies>>;
+
+    /// 保存刷新令牌, 同时将令牌族内其他未使用的刷新令牌标记为已轮换, 令牌族内只有一个有效的刷新令牌,
+    /// 之后再使用旧的刷新令牌会被当作重复使用
+    async fn create_refresh_token(
+        id: &Uuid,
+        family_id: &Uuid,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<RefreshTokens>;
+
+    /// 根据id查询刷新令牌
+    async fn find_refresh_token(id: &Uuid) -> Result<Option<RefreshTokens>>;
+
+    /// 轮换刷新令牌, 只有未使用/未过期/令牌族未注销的令牌才会返回
+    async fn rotate_refresh_token(id: &Uuid) -> Result<Option<RefreshTokens>>;
+
+    /// 注销令牌族
+    async fn revoke_family(id: &Uuid) -> Result<u64>;
+
+    /// 注销属于指定用户的令牌族
+    async fn revoke_family_of_user(id: &Uuid, user_id: &Uuid) -> Result<u64>;
+
+    /// 注销用户所有令牌族, 返回本次注销的令牌族id
+    async fn revoke_families_by_user(user_id: &Uuid) -> Result<Vec<Uuid>>;
+
+    /// 保存已注销令牌, 已存在时返回 false
+    async fn create_revoked(id: &Uuid, user_id: &Uuid, expires_at: &DateTime<Utc>) -> Result<bool>;
+
+    /// 查询所有未过期的已注销令牌
+    async fn find_unexpired_revoked() -> Result<Vec<RevokedTokens>>;
+
+    /// 删除已过期的已注销令牌
+    async fn delete_expired_revoked() -> Result<u64>;
+}
+
+#[async_trait]
+impl ExtTokensRepository for TokensRepository {
+    async fn create_family(
+        id: &Uuid,
+        user_id: &Uuid,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<TokenFamilies> {
+        let row = sqlx::query_as!(
+            TokenFamilies,
+            //language=sql
+            "INSERT INTO token_families(id, user_id, user_agent, ip) VALUES ($1, $2, $3, $4) RETURNING *",
+            id,
+            user_id,
+            user_agent,
+            ip
+        )
+        .fetch_one(&POOL.clone())
+        .await
+        .context("创建令牌族")?;
+
+        Ok(row)
+    }
+
+    async fn touch_family(id: &Uuid, user_agent: Option<&str>, ip: Option<&str>) -> Result<()> {
+        sqlx::query!(
+            //language=sql
+            r#"UPDATE token_families
+               SET last_used_at = current_timestamp,
+                   user_agent   = coalesce($2, user_agent),
+                   ip           = coalesce($3, ip)
+               WHERE id = $1"#,
+            id,
+            user_agent,
+            ip
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("更新令牌族使用时间")?;
+
+        Ok(())
+    }
+
+    async fn find_active_families_by_user(user_id: &Uuid) -> Result<Vec<TokenFamilies>> {
+        let rows = sqlx::query_as!(
+            TokenFamilies,
+            //language=sql
+            r#"SELECT f.*
+               FROM token_families f
+               WHERE f.user_id = $1
+                 AND f.revoked_at IS NULL
+                 AND EXISTS(SELECT 1
+                            FROM refresh_tokens r
+                            WHERE r.family_id = f.id
+                              AND r.rotated_at IS NULL
+                              AND r.expires_at > current_timestamp)
+               ORDER BY f.last_used_at DESC"#,
+            user_id
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询用户会话")?;
+
+        Ok(rows)
+    }
+
+    async fn create_refresh_token(
+        id: &Uuid,
+        family_id: &Uuid,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<RefreshTokens> {
+        let mut tx = POOL.begin().await?;
+        sqlx::query!(
+            //language=sql
+            "UPDATE refresh_tokens SET rotated_at = current_timestamp WHERE family_id = $1 AND rotated_at IS NULL",
+            family_id
+        )
+        .execute(&mut tx)
+        .await
+        .context("轮换令牌族内的刷新令牌")?;
+        let row = sqlx::query_as!(
+            RefreshTokens,
+            //language=sql
+            "INSERT INTO refresh_tokens(id, family_id, expires_at) VALUES ($1, $2, $3) RETURNING *",
+            id,
+            family_id,
+            expires_at
+        )
+        .fetch_one(&mut tx)
+        .await
+        .context("保存刷新令牌")?;
+        tx.commit().await?;
+
+        Ok(row)
+    }
+
+    async fn find_refresh_token(id: &Uuid) -> Result<Option<RefreshTokens>> {
+        let row = sqlx::query_as!(
+            RefreshTokens,
+            //language=sql
+            "SELECT * FROM refresh_tokens WHERE id = $1",
+            id
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("查询刷新令牌")?;
+
+        Ok(row)
+    }
+
+    async fn rotate_refresh_token(id: &Uuid) -> Result<Option<RefreshTokens>> {
+        let row = sqlx::query_as!(
+            RefreshTokens,
+            //language=sql
+            r#"UPDATE refresh_tokens r
+               SET rotated_at = current_timestamp
+               FROM token_families f
+               WHERE r.id = $1
+                 AND r.family_id = f.id
+                 AND r.rotated_at IS NULL
+                 AND r.expires_at > current_timestamp
+                 AND f.revoked_at IS NULL
+               RETURNING r.*"#,
+            id
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("轮换刷新令牌")?;
+
+        Ok(row)
+    }
+
+    async fn revoke_family(id: &Uuid) -> Result<u64> {
+        let result = sqlx::query!(
+            //language=sql
+            "UPDATE token_families SET revoked_at = current_timestamp WHERE id = $1 AND revoked_at IS NULL",
+            id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("注销令牌族")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn revoke_family_of_user(id: &Uuid, user_id: &Uuid) -> Result<u64> {
+        let result = sqlx::query!(
+            //language=sql
+            "UPDATE token_families SET revoked_at = current_timestamp WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            id,
+            user_id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("注销用户令牌族")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn revoke_families_by_user(user_id: &Uuid) -> Result<Vec<Uuid>> {
+        let rows = sqlx::query!(
+            //language=sql
+            "UPDATE token_families SET revoked_at = current_timestamp WHERE user_id = $1 AND revoked_at IS NULL RETURNING id",
+            user_id
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("注销用户所有令牌族")?;
+
+        Ok(rows.into_iter().map(|row| row.id).collect())
+    }
+
+    async fn create_revoked(id: &Uuid, user_id: &Uuid, expires_at: &DateTime<Utc>) -> Result<bool> {
+        let result = sqlx::query!(
+            //language=sql
+            "INSERT INTO revoked_tokens(id, user_id, expires_at) VALUES ($1, $2, $3) ON CONFLICT (id) DO NOTHING",
+            id,
+            user_id,
+            expires_at
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("保存已注销令牌")?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn find_unexpired_revoked() -> Result<Vec<RevokedTokens>> {
+        let rows = sqlx::query_as!(
+            RevokedTokens,
+            //language=sql
+            "SELECT * FROM revoked_tokens WHERE expires_at > current_timestamp"
+        )
+        .fetch_all(&POOL.clone())
+        .await
+        .context("查询未过期的已注销令牌")?;
+
+        Ok(rows)
+    }
+
+    async fn delete_expired_revoked() -> Result<u64> {
+        let result = sqlx::query!(
+            //language=sql
+            "DELETE FROM revoked_tokens WHERE expires_at <= current_timestamp"
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("删除已过期的已注销令牌")?;
+
+        Ok(result.rows_affected())
+    }
+}
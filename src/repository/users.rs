@@ -1,5 +1,6 @@
 use anyhow::*;
 use async_trait::async_trait;
+use uuid::Uuid;
 
 use crate::{
     domain::users::{NewUser, Users},
@@ -10,8 +11,11 @@ pub struct UsersRepository;
 
 #[async_trait]
 pub trait ExtUsersRepository {
-    /// 注册用户
-    async fn create(new_user: &NewUser, password_hash: &str) -> Result<Users>;
+    /// 注册用户并分配角色, 在同一个事务中执行
+    async fn create(new_user: &NewUser, password_hash: &str, role: &str) -> Result<Users>;
+
+    /// 根据id查询用户
+    async fn find_by_id(id: &Uuid) -> Result<Option<Users>>;
 
     /// 根据用户名查询用户
     async fn find_by_username(username: &str) -> Result<Option<Users>>;
@@ -25,13 +29,20 @@ pub trait ExtUsersRepository {
     /// 检查用户是否存在
     async fn exists_by_username(username: &str) -> Result<bool>;
 
+    /// 更新密码哈希
+    async fn update_password_hash(id: &Uuid, password_hash: &str) -> Result<()>;
+
     /// 检查用户是否存在
     async fn exists_by_email(email: &str) -> Result<bool>;
+
+    /// 标记邮箱已验证
+    async fn set_email_verified(id: &Uuid) -> Result<()>;
 }
 
 #[async_trait]
 impl ExtUsersRepository for UsersRepository {
-    async fn create(new_user: &NewUser, password_hash: &str) -> Result<Users> {
+    async fn create(new_user: &NewUser, password_hash: &str, role: &str) -> Result<Users> {
+        let mut tx = POOL.begin().await?;
         let row = sqlx::query_as!(
             Users,
             //language=sql
@@ -41,9 +52,36 @@ impl ExtUsersRepository for UsersRepository {
             &new_user.email,
             password_hash
         )
-            .fetch_one(&POOL.clone())
+            .fetch_one(&mut tx)
             .await
             .context("创建用户")?;
+        let result = sqlx::query!(
+            //language=sql
+            "INSERT INTO user_roles(user_id, role_id) SELECT $1, id FROM roles WHERE name = $2",
+            row.id,
+            role
+        )
+        .execute(&mut tx)
+        .await
+        .context("分配用户角色")?;
+        if result.rows_affected() == 0 {
+            bail!("角色: [{}] 不存在", role);
+        }
+        tx.commit().await?;
+
+        Ok(row)
+    }
+
+    async fn find_by_id(id: &Uuid) -> Result<Option<Users>> {
+        let row = sqlx::query_as!(
+            Users,
+            //language=sql
+            "SELECT * FROM users WHERE id = $1",
+            id
+        )
+        .fetch_optional(&POOL.clone())
+        .await
+        .context("根据id查询用户")?;
 
         Ok(row)
     }
@@ -120,4 +158,31 @@ impl ExtUsersRepository for UsersRepository {
         let exists: Option<bool> = row.exists;
         Ok(exists.unwrap_or_default())
     }
+
+    async fn update_password_hash(id: &Uuid, password_hash: &str) -> Result<()> {
+        sqlx::query!(
+            //language=sql
+            "UPDATE users SET password_hash = $2, updated_at = current_timestamp WHERE id = $1",
+            id,
+            password_hash
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("更新密码哈希")?;
+
+        Ok(())
+    }
+
+    async fn set_email_verified(id: &Uuid) -> Result<()> {
+        sqlx::query!(
+            //language=sql
+            "UPDATE users SET email_verified = true, updated_at = current_timestamp WHERE id = $1",
+            id
+        )
+        .execute(&POOL.clone())
+        .await
+        .context("标记邮箱已验证")?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,240 @@
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{Context, Result};
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::domain::tokens::PersonalAccessTokens;
+use crate::domain::users::Users;
+use crate::security::crypto::Claims;
+use crate::security::guards::PERMISSION_USERS_IMPERSONATE;
+use crate::service::access_tokens::{AccessTokensService, ExtAccessTokensService};
+use crate::service::roles::{ExtRolesService, RolesService};
+use crate::service::users::{ExtUsersService, UsersService};
+use crate::{CRYPTO, REVOCATIONS};
+
+/// `Authorization` 请求头中令牌的前缀
+pub const BEARER: &str = "Bearer ";
+/// 个人访问令牌的前缀, 用于和 JWT 区分
+pub const ACCESS_TOKEN_PREFIX: &str = "pat_";
+
+/// 认证凭据
+#[derive(Debug)]
+pub enum Credential {
+    /// 登录会话签发的访问令牌
+    Session(Claims),
+    /// 个人访问令牌
+    AccessToken(PersonalAccessTokens),
+}
+
+/// 当前登录用户, 认证通过后放入 graphql 请求的 data 中
+#[derive(Debug)]
+pub struct CurrentUser {
+    pub user: Users,
+    pub credential: Credential,
+    /// 令牌中的角色, 个人访问令牌只按权限范围授权, 不携带角色
+    pub roles: Vec<String>,
+    /// 令牌中角色拥有的权限, 个人访问令牌只保留权限范围内的权限
+    pub permissions: Vec<String>,
+    /// 代登录时实际操作的管理员id
+    pub impersonator: Option<Uuid>,
+}
+
+impl CurrentUser {
+    /// 使用登录会话认证时的令牌 claims
+    pub fn claims(&self) -> Option<&Claims> {
+        match &self.credential {
+            Credential::Session(claims) => Some(claims),
+            Credential::AccessToken(_) => None,
+        }
+    }
+}
+
+/// 客户端信息, 每个 graphql 请求都会放入 data 中
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// 部署在反向代理之后时 (`trust_proxy`), 使用 `X-Forwarded-For` 中的最后一个地址作为客户端地址
+    ///
+    /// 最后一个地址由反向代理追加, 之前的地址可以由客户端任意填写, 不能用于登录失败限制
+    pub fn new(
+        remote: Option<SocketAddr>,
+        forwarded_for: Option<&str>,
+        user_agent: Option<String>,
+        trust_proxy: bool,
+    ) -> ClientInfo {
+        let forwarded = forwarded_for
+            .filter(|_| trust_proxy)
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        ClientInfo {
+            ip: forwarded.or_else(|| remote.map(|addr| addr.ip())),
+            user_agent,
+        }
+    }
+}
+
+/// 根据外部账号的用户名或邮箱生成用户名, 只保留用户名允许的字符, 长度不足时补齐
+pub fn username_hint(username: Option<&str>, email: Option<&str>) -> String {
+    let hint = username
+        .or_else(|| email.and_then(|email| email.split('@').next()))
+        .unwrap_or_default();
+    let mut username = hint
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .map(|c| c.to_ascii_lowercase())
+        .take(11)
+        .collect::<String>();
+    while username.len() < 4 {
+        username.push('_');
+    }
+    username
+}
+
+/// 从 `Authorization` 请求头中取出令牌
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let prefix = authorization.get(..BEARER.len())?;
+    if !prefix.eq_ignore_ascii_case(BEARER) {
+        return None;
+    }
+    let token = authorization[BEARER.len()..].trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+/// 根据访问令牌或个人访问令牌认证当前用户
+pub async fn authenticate(token: &str) -> Result<CurrentUser> {
+    if token.starts_with(ACCESS_TOKEN_PREFIX) {
+        return authenticate_access_token(token).await;
+    }
+    let claims = CRYPTO.verify_access_token(token).await?.claims;
+    // 令牌或所在令牌族已被注销
+    if REVOCATIONS.is_revoked(&claims.jti) || REVOCATIONS.is_revoked(&claims.sid) {
+        return Err(AppError::Unauthenticated.into());
+    }
+    let user_id = Uuid::parse_str(&claims.sub).context("令牌中的用户id格式错误")?;
+
+    let user = match UsersService::find_by_id(&user_id).await? {
+        Some(user) if user.active => user,
+        _ => return Err(AppError::Unauthenticated.into()),
+    };
+
+    // 代登录令牌要求管理员仍然有效并且拥有代登录权限
+    let impersonator = match &claims.act {
+        Some(actor) => {
+            let actor_id = Uuid::parse_str(&actor.sub).context("令牌中的操作人id格式错误")?;
+            if !can_impersonate(&actor_id).await? {
+                return Err(AppError::Unauthenticated.into());
+            }
+            Some(actor_id)
+        }
+        None => None,
+    };
+
+    let permissions = RolesService::find_permissions_by_roles(&claims.roles).await?;
+
+    Ok(CurrentUser {
+        user,
+        roles: claims.roles.clone(),
+        credential: Credential::Session(claims),
+        permissions,
+        impersonator,
+    })
+}
+
+/// 用户是否有效并且拥有代登录权限
+async fn can_impersonate(user_id: &Uuid) -> Result<bool> {
+    match UsersService::find_by_id(user_id).await? {
+        Some(user) if user.active => {
+            let roles = RolesService::find_names_by_user(user_id).await?;
+            let permissions = RolesService::find_permissions_by_roles(&roles).await?;
+            Ok(permissions
+                .iter()
+                .any(|p| p == PERMISSION_USERS_IMPERSONATE))
+        }
+        _ => Ok(false),
+    }
+}
+
+/// 根据个人访问令牌认证当前用户, 权限为用户当前权限与令牌权限范围的交集
+async fn authenticate_access_token(token: &str) -> Result<CurrentUser> {
+    let access_token = AccessTokensService::authenticate(token)
+        .await?
+        .ok_or(AppError::Unauthenticated)?;
+
+    let user = match UsersService::find_by_id(&access_token.user_id).await? {
+        Some(user) if user.active => user,
+        _ => return Err(AppError::Unauthenticated.into()),
+    };
+
+    let roles = RolesService::find_names_by_user(&user.id).await?;
+    let permissions = RolesService::find_permissions_by_roles(&roles)
+        .await?
+        .into_iter()
+        .filter(|permission| access_token.scopes.contains(permission))
+        .collect();
+
+    Ok(CurrentUser {
+        user,
+        credential: Credential::AccessToken(access_token),
+        roles: vec![],
+        permissions,
+        impersonator: None,
+    })
+}
+
+/// 解析 `Authorization` 请求头, 请求头缺失或令牌无效时按匿名用户处理
+pub async fn authenticate_header(authorization: Option<String>) -> Option<CurrentUser> {
+    let token = bearer_token(authorization.as_deref()?)?;
+    match authenticate(token).await {
+        Ok(current_user) => Some(current_user),
+        Err(error) => {
+            log::debug!("令牌认证失败: {:#}", error);
+            None
+        }
+    }
+}
+
+#[test]
+fn test_bearer_token() {
+    assert_eq!(bearer_token("Bearer abc.def"), Some("abc.def"));
+    assert_eq!(bearer_token("bearer abc.def "), Some("abc.def"));
+    assert_eq!(bearer_token("Bearer "), None);
+    assert_eq!(bearer_token("Basic abc"), None);
+    assert_eq!(bearer_token("abc"), None);
+}
+
+#[test]
+fn test_client_info() {
+    let remote = "10.0.0.1:1234".parse().ok();
+    let client = ClientInfo::new(remote, Some("203.0.113.7"), None, true);
+    assert_eq!(client.ip, "203.0.113.7".parse().ok());
+    // 客户端伪造的地址在前, 反向代理追加的地址在最后
+    let client = ClientInfo::new(remote, Some("198.51.100.1, 203.0.113.7"), None, true);
+    assert_eq!(client.ip, "203.0.113.7".parse().ok());
+    let client = ClientInfo::new(remote, Some("198.51.100.1, unknown"), None, true);
+    assert_eq!(client.ip, "10.0.0.1".parse().ok());
+    let client = ClientInfo::new(remote, Some("203.0.113.7"), None, false);
+    assert_eq!(client.ip, "10.0.0.1".parse().ok());
+    let client = ClientInfo::new(remote, Some("unknown"), None, true);
+    assert_eq!(client.ip, "10.0.0.1".parse().ok());
+    assert_eq!(ClientInfo::new(None, None, None, true).ip, None);
+}
+
+#[test]
+fn test_username_hint() {
+    assert_eq!(username_hint(Some("Alice.Smith"), None), "alicesmith");
+    assert_eq!(username_hint(None, Some("bo@example.com")), "bo__");
+    assert_eq!(username_hint(None, None), "____");
+    assert_eq!(
+        username_hint(Some("a-very-long-user-name"), Some("x@example.com")),
+        "a-very-long"
+    );
+}
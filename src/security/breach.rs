@@ -0,0 +1,168 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// SHA-1 的长度
+const SHA1_LEN: usize = 20;
+
+/// 目录格式中文件名使用的 SHA-1 前缀长度 (十六进制字符)
+const PREFIX_LEN: usize = 5;
+
+/// 已泄露密码列表 (Have I Been Pwned 下载格式)
+pub enum BreachedPasswords {
+    /// 未配置, 不检查
+    Disabled,
+    /// 单个文件, 每行 `SHA-1:次数`, 启动时加载到内存 (排序后二分查找)
+    Memory(Vec<[u8; SHA1_LEN]>),
+    /// 按 SHA-1 前 5 位分文件的目录, 每行 `后 35 位:次数`, 检查时读取对应文件
+    Directory { dir: PathBuf, min_count: u64 },
+}
+
+impl BreachedPasswords {
+    /// 加载列表, 泄露次数少于 `min_count` 的密码不处理
+    pub fn load(path: impl AsRef<Path>, min_count: u64) -> Result<BreachedPasswords> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return Ok(BreachedPasswords::Directory {
+                dir: path.to_path_buf(),
+                min_count,
+            });
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let mut hashes = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (hash, count) =
+                parse_line(line).with_context(|| format!("第 {} 行格式错误", index + 1))?;
+            if count >= min_count {
+                hashes.push(
+                    decode_hex(hash).with_context(|| format!("第 {} 行格式错误", index + 1))?,
+                );
+            }
+        }
+        hashes.sort_unstable();
+        hashes.dedup();
+        Ok(BreachedPasswords::Memory(hashes))
+    }
+
+    /// 密码是否在已泄露密码列表中
+    pub async fn contains(&self, password: &str) -> Result<bool> {
+        let digest = openssl::sha::sha1(password.as_bytes());
+        match self {
+            BreachedPasswords::Disabled => Ok(false),
+            BreachedPasswords::Memory(hashes) => Ok(hashes.binary_search(&digest).is_ok()),
+            BreachedPasswords::Directory { dir, min_count } => {
+                let hex = encode_hex(&digest);
+                let (prefix, suffix) = hex.split_at(PREFIX_LEN);
+                let file = dir.join(format!("{}.txt", prefix));
+                let content = match tokio::fs::read_to_string(&file).await {
+                    Ok(content) => content,
+                    // 下载的列表中每个前缀都有文件, 缺少文件说明列表不完整
+                    Err(error) if error.kind() == ErrorKind::NotFound => {
+                        log::warn!("已泄露密码列表缺少文件: [{}]", file.display());
+                        return Ok(false);
+                    }
+                    Err(error) => {
+                        return Err(error)
+                            .with_context(|| format!("读取已泄露密码列表: [{}]", file.display()))
+                    }
+                };
+                for line in content.lines() {
+                    if let Ok((hash, count)) = parse_line(line.trim()) {
+                        if hash.eq_ignore_ascii_case(suffix) {
+                            return Ok(count >= *min_count);
+                        }
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BreachedPasswords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreachedPasswords::Disabled => write!(f, "未启用"),
+            BreachedPasswords::Memory(hashes) => write!(f, "{} 条", hashes.len()),
+            BreachedPasswords::Directory { dir, .. } => write!(f, "{}", dir.display()),
+        }
+    }
+}
+
+/// 解析 `哈希:次数`, 没有次数时按 1 次处理
+fn parse_line(line: &str) -> Result<(&str, u64)> {
+    match line.split_once(':') {
+        Some((hash, count)) => Ok((hash, count.trim().parse()?)),
+        None => Ok((line, 1)),
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<[u8; SHA1_LEN]> {
+    if hex.len() != SHA1_LEN * 2 || !hex.is_ascii() {
+        bail!("SHA-1 长度错误: [{}]", hex);
+    }
+    let mut bytes = [0u8; SHA1_LEN];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(bytes)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+#[tokio::test]
+async fn test_breached_passwords() {
+    // SHA-1("password") = 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+    // SHA-1("123456") = 7C4A8D09CA3762AF61E59520943DC26494F8941B
+    let dir = std::env::temp_dir().join(format!("breach-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // 单个文件
+    let file = dir.join("pwned-passwords.txt");
+    std::fs::write(
+        &file,
+        "7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\n\
+         5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8:3\n",
+    )
+    .unwrap();
+    let breached = BreachedPasswords::load(&file, 1).unwrap();
+    assert!(breached.contains("password").await.unwrap());
+    assert!(breached.contains("123456").await.unwrap());
+    assert!(!breached.contains("zgmwqk4rTv").await.unwrap());
+    // 泄露次数少于 min_count
+    let breached = BreachedPasswords::load(&file, 10).unwrap();
+    assert!(!breached.contains("password").await.unwrap());
+    assert!(breached.contains("123456").await.unwrap());
+
+    // 按前缀分文件的目录
+    let range = dir.join("range");
+    std::fs::create_dir_all(&range).unwrap();
+    std::fs::write(
+        range.join("5BAA6.txt"),
+        "1D2AC1C3C4E41C7ECE8F0B9C2A5D08D4B1E:2\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:3\r\n",
+    )
+    .unwrap();
+    let breached = BreachedPasswords::load(&range, 1).unwrap();
+    assert!(breached.contains("password").await.unwrap());
+    // 缺少前缀文件
+    assert!(!breached.contains("123456").await.unwrap());
+    let breached = BreachedPasswords::load(&range, 10).unwrap();
+    assert!(!breached.contains("password").await.unwrap());
+
+    // 格式错误
+    std::fs::write(&file, "not-a-hash:1\n").unwrap();
+    assert!(BreachedPasswords::load(&file, 1).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,56 +1,276 @@
-use anyhow::{Context, Result};
-use argon2::Config;
-use chrono::{Duration, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, TokenData, Validation};
+use anyhow::{bail, Context, Result};
+use argon2::{Config, Variant};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Header, TokenData, Validation};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::symm::{self, Cipher};
+use rand::RngCore;
 use serde::Deserialize;
 use serde::Serialize;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::common::error::errors::AppError;
+use crate::security::hash_pool::HashPool;
+use crate::security::keys::JwtKeyring;
+
+/// 每个密码哈希随机生成的盐长度
+pub const SALT_LEN: usize = 16;
+
+/// 随机令牌 (重置密码令牌等) 长度
+pub const TOKEN_LEN: usize = 32;
+
+/// AES-GCM 随机数长度
+const NONCE_LEN: usize = 12;
+/// AES-GCM 认证标签长度
+const TAG_LEN: usize = 16;
+
 #[derive(Debug)]
 pub struct CryptoService {
+    /// 旧版本所有用户共用的密码盐, 仅用于识别需要升级的密码哈希
     pub hash_salt: Arc<String>,
     pub hash_secret: Arc<String>,
-    pub jwt_secret: Arc<String>,
+    pub hash_params: Arc<HashParams>,
+    /// 用户不存在时用于验证密码的哈希, 参数与当前配置一致
+    pub dummy_hash: Arc<String>,
+    pub hash_pool: Arc<HashPool>,
+    pub jwt_keys: Arc<JwtKeyring>,
     pub access_expires: Arc<Duration>,
     pub refash_expires: Arc<Duration>,
     pub issuer: Arc<String>,
+    pub audience: Arc<String>,
+    /// 加密敏感数据 (如 TOTP 秘钥) 的 AES-256-GCM 秘钥
+    pub encryption_key: Arc<[u8; 32]>,
+    /// 两步验证令牌有效时长
+    pub mfa_expires: Arc<Duration>,
+    /// 代登录令牌有效时长
+    pub impersonation_expires: Arc<Duration>,
 }
+
+/// 令牌类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    /// 访问令牌
+    Access,
+    /// 刷新令牌
+    Refresh,
+    /// 两步验证令牌, 密码验证通过后用于换取访问令牌
+    Mfa,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    exp: i64,    // 必填（验证中的defaultate_exp默认为true）。到期时间（以UTC时间戳记）
-    iat: i64,    // 可选 签发时间（以UTC时间戳记）
-    iss: String, // 可选 签发人
-    nbf: i64,    // 可选 生效时间（以UTC时间戳记）
-    sub: String, // 可选 用户
+    pub exp: i64,    // 必填（验证中的defaultate_exp默认为true）。到期时间（以UTC时间戳记）
+    pub iat: i64,    // 可选 签发时间（以UTC时间戳记）
+    pub iss: String, // 可选 签发人
+    pub nbf: i64,    // 可选 生效时间（以UTC时间戳记）
+    pub sub: String, // 可选 用户
+    pub aud: String, // 可选 受众
+    pub jti: Uuid,   // 令牌唯一标识
+    pub sid: Uuid,   // 令牌族 (会话) 标识
+    pub typ: TokenType, // 令牌类型
+    #[serde(default)]
+    pub roles: Vec<String>, // 用户角色
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>, // 代登录时的实际操作人
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>, // 最近一次验证密码的时间（以UTC时间戳记）
+}
+
+/// 代登录令牌中的实际操作人 (RFC 8693 `act`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String, // 操作人 (管理员) id
+}
+
+/// 令牌主体, 签发令牌时写入 claims
+#[derive(Debug, Clone)]
+pub struct TokenSubject {
+    pub user_id: Uuid,
+    /// 令牌族 (会话) 标识
+    pub sid: Uuid,
+    /// 用户角色
+    pub roles: Vec<String>,
+    /// 最近一次验证密码的时间, 敏感操作要求在有效期内
+    pub auth_time: Option<DateTime<Utc>>,
+}
+
+/// argon2 参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub variant: Variant,
+    /// 内存开销 (KiB)
+    pub mem_cost: u32,
+    /// 迭代次数
+    pub time_cost: u32,
+    /// 并行度
+    pub lanes: u32,
+}
+
+impl Default for HashParams {
+    fn default() -> Self {
+        let config = Config::default();
+        HashParams {
+            variant: config.variant,
+            mem_cost: config.mem_cost,
+            time_cost: config.time_cost,
+            lanes: config.lanes,
+        }
+    }
+}
+
+impl HashParams {
+    /// 是否弱于 `other`, 算法不同也视为需要升级; 并行度只影响计算方式, 不参与比较
+    fn weaker_than(&self, other: &HashParams) -> bool {
+        self.variant != other.variant
+            || self.mem_cost < other.mem_cost
+            || self.time_cost < other.time_cost
+    }
+}
+
+/// 编码后的 argon2 密码哈希 `$argon2i$v=19$m=4096,t=3,p=1$<salt>$<hash>`
+#[derive(Debug)]
+struct EncodedHash {
+    params: HashParams,
+    salt: Vec<u8>,
+}
+
+impl EncodedHash {
+    fn decode(encoded: &str) -> Option<EncodedHash> {
+        let parts = encoded.split('$').collect::<Vec<_>>();
+        // 旧版本的哈希没有 v=19 部分
+        let (variant, params, salt) = match parts.as_slice() {
+            ["", variant, _, params, salt, _] | ["", variant, params, salt, _] => {
+                (variant, params, salt)
+            }
+            _ => return None,
+        };
+
+        let mut result = HashParams {
+            variant: Variant::from_str(variant).ok()?,
+            ..HashParams::default()
+        };
+        for param in params.split(',') {
+            let mut kv = param.splitn(2, '=');
+            let (key, value) = (kv.next()?, kv.next()?.parse::<u32>().ok()?);
+            match key {
+                "m" => result.mem_cost = value,
+                "t" => result.time_cost = value,
+                "p" => result.lanes = value,
+                _ => return None,
+            }
+        }
+
+        let salt = base64::decode_config(salt, base64::STANDARD_NO_PAD).ok()?;
+        Some(EncodedHash {
+            params: result,
+            salt,
+        })
+    }
+}
+
+/// 计算用户不存在时用于验证的密码哈希, 密码为随机值, 不会验证通过
+pub fn dummy_password_hash(secret: &str, params: &HashParams) -> Result<String> {
+    let mut password = [0u8; TOKEN_LEN];
+    let mut salt = [0u8; SALT_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut password);
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    let config = Config {
+        secret: secret.as_bytes(),
+        variant: params.variant,
+        mem_cost: params.mem_cost,
+        time_cost: params.time_cost,
+        lanes: params.lanes,
+        ..Config::default()
+    };
+    argon2::hash_encoded(&password, &salt, &config).context("计算密码哈希异常!")
+}
+
+/// 一次签发的 access_token 和 refresh_token
+#[derive(Debug)]
+pub struct JwtPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// refresh_token 的 jti
+    pub refresh_jti: Uuid,
+    /// access_token 有效时长
+    pub expires: Duration,
+    /// refresh_token 过期时间
+    pub refresh_expires_at: DateTime<Utc>,
 }
 
 impl CryptoService {
-    /// 计算密码哈希
+    /// 计算密码哈希, 每次计算都使用随机盐, `hash_secret` 作为秘钥(pepper)参与计算
     pub async fn generate_password_hash(&self, pwd: &str) -> Result<String> {
-        let config = Config {
-            secret: self.hash_secret.as_bytes(),
-            ..Config::default()
-        };
-        let salt = self.hash_salt.as_bytes();
-        argon2::hash_encoded(pwd.as_bytes(), salt, &config).context("计算密码哈希异常!")
+        let secret = self.hash_secret.clone();
+        let params = *self.hash_params;
+        let pwd = pwd.to_owned();
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+
+        self.hash_pool
+            .run(move || {
+                let config = Config {
+                    secret: secret.as_bytes(),
+                    variant: params.variant,
+                    mem_cost: params.mem_cost,
+                    time_cost: params.time_cost,
+                    lanes: params.lanes,
+                    ..Config::default()
+                };
+                argon2::hash_encoded(pwd.as_bytes(), &salt, &config)
+            })
+            .await?
+            .context("计算密码哈希异常!")
+    }
+
+    /// 检查密码哈希是否需要在用户下次登录成功时重新计算
+    pub fn needs_rehash(&self, encoded: &str) -> bool {
+        match EncodedHash::decode(encoded) {
+            // 使用旧版本全局盐计算的哈希, 或者参数弱于当前配置
+            Some(hash) => {
+                hash.salt == self.hash_salt.as_bytes() || hash.params.weaker_than(&self.hash_params)
+            }
+            None => true,
+        }
     }
 
     /// 验证密码哈希
     pub async fn verify_password(&self, pwd: &str, encoded: &str) -> Result<bool> {
-        let secret = self.hash_secret.as_bytes();
-        let pwd = pwd.as_bytes();
-        argon2::verify_encoded_ext(encoded, pwd, secret, &[]).context("验证密码哈希异常!")
+        let secret = self.hash_secret.clone();
+        let pwd = pwd.to_owned();
+        let encoded = encoded.to_owned();
+
+        self.hash_pool
+            .run(move || {
+                argon2::verify_encoded_ext(&encoded, pwd.as_bytes(), secret.as_bytes(), &[])
+            })
+            .await?
+            .context("验证密码哈希异常!")
     }
 
-    /// 生成jwt (access_token, refash_token)
-    pub async fn generate_jwt(&self, user_id: &Uuid) -> Result<(String, String, Duration)> {
-        let secret = &EncodingKey::from_secret(self.jwt_secret.as_bytes());
+    /// 用户不存在时验证假的密码哈希, 耗时与验证真实的密码哈希相同, 避免通过响应时间判断用户是否存在
+    pub async fn verify_dummy_password(&self, pwd: &str) -> Result<()> {
+        self.verify_password(pwd, &self.dummy_hash).await?;
+        Ok(())
+    }
+
+    /// 为令牌主体生成jwt (access_token, refresh_token)
+    pub async fn generate_jwt(&self, subject: &TokenSubject) -> Result<JwtPair> {
+        let key = self.jwt_keys.active();
+        let secret = &key.encoding;
         let iss = self.issuer.to_string();
         let expires = *self.access_expires;
 
-        let sub = user_id.to_string();
-        let header = Header::default();
+        let sub = subject.user_id.to_string();
+        let header = Header {
+            kid: key.kid.clone(),
+            ..Header::new(key.algorithm)
+        };
         let now = Utc::now();
         let exp = now + expires;
         let claims = Claims {
@@ -59,60 +279,525 @@ impl CryptoService {
             nbf: now.timestamp(),
             iss,
             sub,
+            aud: self.audience.to_string(),
+            jti: Uuid::new_v4(),
+            sid: subject.sid,
+            typ: TokenType::Access,
+            roles: subject.roles.clone(),
+            act: None,
+            auth_time: subject.auth_time.map(|auth_time| auth_time.timestamp()),
         };
         let access_token = jsonwebtoken::encode(&header, &claims, secret)?;
 
-        let expires = *self.refash_expires;
-        let exp = now + expires;
+        let refresh_expires_at = now + *self.refash_expires;
         let claims = Claims {
-            exp: exp.timestamp(),
+            exp: refresh_expires_at.timestamp(),
+            jti: Uuid::new_v4(),
+            typ: TokenType::Refresh,
             ..claims
         };
-        let refash_token = jsonwebtoken::encode(&header, &claims, secret)?;
-        Ok((access_token, refash_token, expires))
+        let refresh_token = jsonwebtoken::encode(&header, &claims, secret)?;
+        Ok(JwtPair {
+            access_token,
+            refresh_token,
+            refresh_jti: claims.jti,
+            expires,
+            refresh_expires_at,
+        })
+    }
+
+    /// 对外公开的签名公钥 (JWKS), 对称秘钥不会出现在其中
+    pub fn jwks(&self) -> JwkSet {
+        self.jwt_keys.jwks()
+    }
+
+    /// 生成两步验证令牌, 只能用于提交两步验证码
+    pub async fn generate_mfa_token(&self, user_id: &Uuid) -> Result<(String, Duration)> {
+        let key = self.jwt_keys.active();
+        let header = Header {
+            kid: key.kid.clone(),
+            ..Header::new(key.algorithm)
+        };
+        let now = Utc::now();
+        let claims = Claims {
+            exp: (now + *self.mfa_expires).timestamp(),
+            iat: now.timestamp(),
+            nbf: now.timestamp(),
+            iss: self.issuer.to_string(),
+            sub: user_id.to_string(),
+            aud: self.audience.to_string(),
+            jti: Uuid::new_v4(),
+            sid: Uuid::nil(),
+            typ: TokenType::Mfa,
+            roles: vec![],
+            act: None,
+            auth_time: None,
+        };
+        let token = jsonwebtoken::encode(&header, &claims, &key.encoding)?;
+        Ok((token, *self.mfa_expires))
+    }
+
+    /// 生成代登录访问令牌, `act` 中记录实际操作的管理员, 不签发刷新令牌
+    pub async fn generate_impersonation_token(
+        &self,
+        subject: &TokenSubject,
+        actor_id: &Uuid,
+    ) -> Result<(String, Duration)> {
+        let key = self.jwt_keys.active();
+        let header = Header {
+            kid: key.kid.clone(),
+            ..Header::new(key.algorithm)
+        };
+        let now = Utc::now();
+        let claims = Claims {
+            exp: (now + *self.impersonation_expires).timestamp(),
+            iat: now.timestamp(),
+            nbf: now.timestamp(),
+            iss: self.issuer.to_string(),
+            sub: subject.user_id.to_string(),
+            aud: self.audience.to_string(),
+            jti: Uuid::new_v4(),
+            sid: subject.sid,
+            typ: TokenType::Access,
+            roles: subject.roles.clone(),
+            act: Some(Actor {
+                sub: actor_id.to_string(),
+            }),
+            auth_time: None,
+        };
+        let token = jsonwebtoken::encode(&header, &claims, &key.encoding)?;
+        Ok((token, *self.impersonation_expires))
+    }
+
+    /// 访问令牌 (包括代登录令牌) 的最长有效期, 注销令牌族时至少保留这么久
+    pub fn max_access_expires(&self) -> Duration {
+        (*self.access_expires).max(*self.impersonation_expires)
+    }
+
+    /// 验证访问令牌
+    pub async fn verify_access_token(&self, token: &str) -> Result<TokenData<Claims>> {
+        self.verify_jwt(token, TokenType::Access).await
+    }
+
+    /// 验证刷新令牌
+    pub async fn verify_refresh_token(&self, token: &str) -> Result<TokenData<Claims>> {
+        self.verify_jwt(token, TokenType::Refresh).await
+    }
+
+    /// 验证两步验证令牌
+    pub async fn verify_mfa_token(&self, token: &str) -> Result<TokenData<Claims>> {
+        self.verify_jwt(token, TokenType::Mfa).await
     }
 
-    pub async fn verify_jwt(&self, token: &str) -> Result<TokenData<Claims>> {
-        let secret = &DecodingKey::from_secret(self.jwt_secret.as_bytes());
-        Ok(jsonwebtoken::decode::<Claims>(
-            token,
-            secret,
-            &Validation::default(),
-        )?)
+    /// 加密敏感数据, 返回 base64(随机数 + 密文 + 认证标签)
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String> {
+        self.encrypt_for(&[], plaintext)
+    }
+
+    /// 解密 `encrypt` 加密的数据
+    pub fn decrypt(&self, encoded: &str) -> Result<Vec<u8>> {
+        self.decrypt_for(&[], encoded)
+    }
+
+    /// 按用途加密数据, `purpose` 作为附加认证数据, 只能按相同的用途解密,
+    /// 避免一种用途的密文被当作另一种用途使用
+    pub fn encrypt_for(&self, purpose: &[u8], plaintext: &[u8]) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = symm::encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &*self.encryption_key,
+            Some(&nonce),
+            purpose,
+            plaintext,
+            &mut tag,
+        )?;
+        Ok(base64::encode([&nonce[..], &ciphertext, &tag].concat()))
+    }
+
+    /// 解密 `encrypt_for` 按相同用途加密的数据
+    pub fn decrypt_for(&self, purpose: &[u8], encoded: &str) -> Result<Vec<u8>> {
+        let data = base64::decode(encoded)?;
+        if data.len() < NONCE_LEN + TAG_LEN {
+            bail!("密文长度错误");
+        }
+        let (nonce, rest) = data.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        symm::decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &*self.encryption_key,
+            Some(nonce),
+            purpose,
+            ciphertext,
+            tag,
+        )
+        .context("解密失败")
+    }
+
+    /// 生成随机令牌, base64url 编码
+    pub fn generate_token(&self) -> String {
+        let mut token = [0u8; TOKEN_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut token);
+        base64::encode_config(token, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// 计算随机令牌 (恢复码等) 的哈希, 令牌本身熵足够高, 使用 HMAC-SHA256 即可
+    pub fn hash_token(&self, token: &str) -> Result<String> {
+        let key = PKey::hmac(self.hash_secret.as_bytes())?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(token.as_bytes())?;
+        Ok(signer
+            .sign_to_vec()?
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    /// 验证jwt签名/签发人/受众, 并且要求令牌类型一致
+    async fn verify_jwt(&self, token: &str, typ: TokenType) -> Result<TokenData<Claims>> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let mut data = Err(anyhow::anyhow!(
+            "jwt 秘钥id: [{:?}] 不存在或已停用",
+            header.kid
+        ));
+        for key in self.jwt_keys.find(header.kid.as_deref()) {
+            let mut validation = Validation::new(key.algorithm);
+            validation.validate_nbf = true;
+            validation.set_issuer(&[self.issuer.as_str()]);
+            validation.set_audience(&[self.audience.as_str()]);
+
+            data = jsonwebtoken::decode::<Claims>(token, &key.decoding, &validation)
+                .map_err(Into::into);
+            if data.is_ok() {
+                break;
+            }
+        }
+        let data = data?;
+        if data.claims.typ != typ {
+            return Err(AppError::TokenTypeMismatch.into());
+        }
+        Ok(data)
     }
 }
 
-#[tokio::test]
-async fn test_generate_password_hash() {
-    let crypto_service = CryptoService {
-        hash_salt: Arc::new("test_generate_password_hash".to_string()),
-        hash_secret: Arc::new("test_generate_password_hash".to_string()),
-        jwt_secret: Arc::new("test_generate_password_hash".to_string()),
+/// 测试使用的加密服务, 各个测试只覆盖需要的字段
+#[cfg(test)]
+fn test_crypto() -> CryptoService {
+    use crate::security::keys::JwtKey;
+
+    let hash_params = HashParams::default();
+    CryptoService {
+        hash_salt: Arc::new("test-salt".to_string()),
+        hash_secret: Arc::new("test-secret".to_string()),
+        hash_params: Arc::new(hash_params),
+        dummy_hash: Arc::new(dummy_password_hash("test-secret", &hash_params).unwrap()),
+        hash_pool: Arc::new(HashPool::new(2, std::time::Duration::from_secs(5))),
+        jwt_keys: Arc::new(JwtKeyring::single(JwtKey::from_secret(
+            None,
+            "test-jwt-secret",
+        ))),
         access_expires: Arc::new(Duration::minutes(30)),
         refash_expires: Arc::new(Duration::days(7)),
         issuer: Arc::new("test".to_string()),
-    };
+        audience: Arc::new("test".to_string()),
+        encryption_key: Arc::new([7u8; 32]),
+        mfa_expires: Arc::new(Duration::minutes(5)),
+        impersonation_expires: Arc::new(Duration::minutes(15)),
+    }
+}
+
+#[tokio::test]
+async fn test_generate_password_hash() {
+    let crypto_service = test_crypto();
 
     let pwd = "test_generate_password_hash";
     let encoded = crypto_service.generate_password_hash(pwd).await.unwrap();
     let x = crypto_service.verify_password(pwd, &encoded).await.unwrap();
     assert!(x);
+    assert!(!crypto_service.needs_rehash(&encoded));
+
+    // 用户不存在时验证假的密码哈希, 参数与当前配置一致
+    assert!(!crypto_service.needs_rehash(&crypto_service.dummy_hash));
+    crypto_service.verify_dummy_password(pwd).await.unwrap();
+
+    // 相同的密码每次计算出的哈希都不同
+    let other = crypto_service.generate_password_hash(pwd).await.unwrap();
+    assert_ne!(encoded, other);
+    assert_eq!(EncodedHash::decode(&other).unwrap().salt.len(), SALT_LEN);
+
+    // 使用旧版本全局盐计算的哈希需要升级
+    let config = Config {
+        secret: crypto_service.hash_secret.as_bytes(),
+        ..Config::default()
+    };
+    let legacy =
+        argon2::hash_encoded(pwd.as_bytes(), crypto_service.hash_salt.as_bytes(), &config).unwrap();
+    assert!(crypto_service.verify_password(pwd, &legacy).await.unwrap());
+    assert!(crypto_service.needs_rehash(&legacy));
 }
 
 #[tokio::test]
 async fn test_jwt() {
-    let crypto_service = CryptoService {
-        hash_salt: Arc::new("test_generate_password_hash".to_string()),
-        hash_secret: Arc::new("test_generate_password_hash".to_string()),
-        jwt_secret: Arc::new("your-256-bit-secret".to_string()),
-        access_expires: Arc::new(Duration::minutes(30)),
-        refash_expires: Arc::new(Duration::days(7)),
-        issuer: Arc::new("test".to_string()),
-    };
+    let crypto_service = test_crypto();
 
-    let (a, r, _) = crypto_service.generate_jwt(&Uuid::new_v4()).await.unwrap();
-    let verify = crypto_service.verify_jwt(a.as_str()).await.is_ok();
-    assert!(verify);
-    let verify = crypto_service.verify_jwt(r.as_str()).await.is_ok();
+    let sid = Uuid::new_v4();
+    let subject = TokenSubject {
+        user_id: Uuid::new_v4(),
+        sid,
+        roles: vec!["user".to_string()],
+        auth_time: Some(Utc::now()),
+    };
+    let pair = crypto_service.generate_jwt(&subject).await.unwrap();
+    let verify = crypto_service
+        .verify_access_token(&pair.access_token)
+        .await
+        .is_ok();
     assert!(verify);
+    let refresh = crypto_service
+        .verify_refresh_token(&pair.refresh_token)
+        .await
+        .unwrap();
+    assert_eq!(refresh.claims.jti, pair.refresh_jti);
+    assert_eq!(refresh.claims.sid, sid);
+    assert_eq!(refresh.claims.roles, subject.roles);
+    // 刷新令牌保留最近一次验证密码的时间
+    assert_eq!(
+        refresh.claims.auth_time,
+        subject.auth_time.map(|auth_time| auth_time.timestamp())
+    );
+
+    // 访问令牌与刷新令牌不能互换使用
+    let error = crypto_service
+        .verify_access_token(&pair.refresh_token)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AppError>(),
+        Some(AppError::TokenTypeMismatch)
+    ));
+    let error = crypto_service
+        .verify_refresh_token(&pair.access_token)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AppError>(),
+        Some(AppError::TokenTypeMismatch)
+    ));
+
+    // 两步验证令牌不能当作访问令牌使用
+    let (mfa_token, _) = crypto_service
+        .generate_mfa_token(&subject.user_id)
+        .await
+        .unwrap();
+    let mfa = crypto_service.verify_mfa_token(&mfa_token).await.unwrap();
+    assert_eq!(mfa.claims.sub, subject.user_id.to_string());
+    let error = crypto_service
+        .verify_access_token(&mfa_token)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AppError>(),
+        Some(AppError::TokenTypeMismatch)
+    ));
+    assert!(crypto_service
+        .verify_mfa_token(&pair.access_token)
+        .await
+        .is_err());
+
+    // 代登录令牌
+    let actor_id = Uuid::new_v4();
+    let (token, _) = crypto_service
+        .generate_impersonation_token(&subject, &actor_id)
+        .await
+        .unwrap();
+    let claims = crypto_service
+        .verify_access_token(&token)
+        .await
+        .unwrap()
+        .claims;
+    assert_eq!(claims.sub, subject.user_id.to_string());
+    assert_eq!(
+        claims.act,
+        Some(Actor {
+            sub: actor_id.to_string()
+        })
+    );
+    assert_eq!(refresh.claims.act, None);
+
+    // 加密 / 解密
+    let encrypted = crypto_service.encrypt(b"totp-secret").unwrap();
+    assert_ne!(encrypted, crypto_service.encrypt(b"totp-secret").unwrap());
+    assert_eq!(crypto_service.decrypt(&encrypted).unwrap(), b"totp-secret");
+    let mut tampered = base64::decode(&encrypted).unwrap();
+    tampered[NONCE_LEN] ^= 1;
+    assert!(crypto_service.decrypt(&base64::encode(tampered)).is_err());
+
+    // 不同用途的密文不能互相解密
+    let state = crypto_service.encrypt_for(b"oidc-state", b"state").unwrap();
+    assert_eq!(
+        crypto_service.decrypt_for(b"oidc-state", &state).unwrap(),
+        b"state"
+    );
+    assert!(crypto_service.decrypt(&state).is_err());
+    assert!(crypto_service
+        .decrypt_for(b"oidc-state", &encrypted)
+        .is_err());
+}
+
+#[test]
+fn test_max_access_expires() {
+    let mut crypto_service = test_crypto();
+    assert_eq!(crypto_service.max_access_expires(), Duration::minutes(30));
+    // 代登录令牌有效期更长时, 注销需要保留到代登录令牌过期
+    crypto_service.impersonation_expires = Arc::new(Duration::hours(2));
+    assert_eq!(crypto_service.max_access_expires(), Duration::hours(2));
+}
+
+#[tokio::test]
+async fn test_password_hash_params() {
+    let mut crypto_service = CryptoService {
+        hash_params: Arc::new(HashParams {
+            variant: Variant::Argon2id,
+            mem_cost: 1024,
+            time_cost: 2,
+            lanes: 2,
+        }),
+        ..test_crypto()
+    };
+
+    let pwd = "test_password_hash_params";
+    let encoded = crypto_service.generate_password_hash(pwd).await.unwrap();
+    assert!(encoded.starts_with("$argon2id$v=19$m=1024,t=2,p=2$"));
+    assert_eq!(
+        EncodedHash::decode(&encoded).unwrap().params,
+        *crypto_service.hash_params
+    );
+    assert!(!crypto_service.needs_rehash(&encoded));
+
+    // 调低参数不需要重新计算
+    crypto_service.hash_params = Arc::new(HashParams {
+        mem_cost: 512,
+        ..*crypto_service.hash_params
+    });
+    assert!(!crypto_service.needs_rehash(&encoded));
+
+    // 调高参数或者更换算法后需要重新计算
+    crypto_service.hash_params = Arc::new(HashParams {
+        time_cost: 3,
+        ..*crypto_service.hash_params
+    });
+    assert!(crypto_service.needs_rehash(&encoded));
+    crypto_service.hash_params = Arc::new(HashParams {
+        variant: Variant::Argon2i,
+        ..HashParams::default()
+    });
+    assert!(crypto_service.needs_rehash(&encoded));
+
+    // 旧参数计算的哈希仍然可以验证
+    assert!(crypto_service.verify_password(pwd, &encoded).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_asymmetric_jwt() {
+    use jsonwebtoken::jwk::AlgorithmParameters;
+    use jsonwebtoken::{Algorithm, DecodingKey};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+
+    use crate::security::keys::JwtKey;
+
+    let rsa = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let ec = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let ed = PKey::generate_ed25519().unwrap();
+
+    for (algorithm, pkey) in [
+        (Algorithm::RS256, rsa),
+        (Algorithm::ES256, ec),
+        (Algorithm::EdDSA, ed),
+    ] {
+        let pem = pkey.private_key_to_pem_pkcs8().unwrap();
+        let kid = Some(format!("{:?}", algorithm));
+        let jwt_key = JwtKey::from_private_pem(kid.clone(), algorithm, &pem).unwrap();
+        let crypto_service = CryptoService {
+            jwt_keys: Arc::new(JwtKeyring::single(jwt_key)),
+            ..test_crypto()
+        };
+
+        let subject = TokenSubject {
+            user_id: Uuid::new_v4(),
+            sid: Uuid::new_v4(),
+            roles: vec![],
+            auth_time: None,
+        };
+        let pair = crypto_service.generate_jwt(&subject).await.unwrap();
+        let header = jsonwebtoken::decode_header(&pair.access_token).unwrap();
+        assert_eq!(header.alg, algorithm);
+        assert_eq!(header.kid, kid);
+        assert!(crypto_service
+            .verify_access_token(&pair.access_token)
+            .await
+            .is_ok());
+
+        // 其他服务只凭 JWKS 中的公钥即可验证令牌
+        let jwks = crypto_service.jwks();
+        assert_eq!(jwks.keys.len(), 1);
+        let jwk = jwks.find(kid.as_deref().unwrap()).unwrap();
+        assert!(!matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)));
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&["test"]);
+        let decoding = DecodingKey::from_jwk(jwk).unwrap();
+        assert!(jsonwebtoken::decode::<Claims>(&pair.access_token, &decoding, &validation).is_ok());
+    }
+
+    // 私钥类型与算法不一致
+    let ed = PKey::generate_ed25519().unwrap();
+    let pem = ed.private_key_to_pem_pkcs8().unwrap();
+    assert!(JwtKey::from_private_pem(None, Algorithm::RS256, &pem).is_err());
+}
+
+#[tokio::test]
+async fn test_jwt_key_rotation() {
+    use crate::security::keys::JwtKey;
+
+    let crypto_service = |keys: Vec<JwtKey>, active_kid: Option<&str>| CryptoService {
+        jwt_keys: Arc::new(JwtKeyring::new(keys, active_kid).unwrap()),
+        ..test_crypto()
+    };
+    let old_key = || JwtKey::from_secret(Some("old".to_string()), "old-secret");
+    let new_key = || JwtKey::from_secret(Some("new".to_string()), "new-secret");
+    let subject = TokenSubject {
+        user_id: Uuid::new_v4(),
+        sid: Uuid::new_v4(),
+        roles: vec![],
+        auth_time: None,
+    };
+
+    // 轮换前: 只有旧秘钥, 以及更早签发的没有 kid 的令牌
+    let before = crypto_service(vec![old_key()], None);
+    let old_token = before.generate_jwt(&subject).await.unwrap().access_token;
+    let legacy = crypto_service(vec![JwtKey::from_secret(None, "old-secret")], None);
+    let legacy_token = legacy.generate_jwt(&subject).await.unwrap().access_token;
+
+    // 轮换中: 使用新秘钥签发, 旧秘钥签发的令牌仍然有效
+    let rotating = crypto_service(vec![old_key(), new_key()], Some("new"));
+    let new_token = rotating.generate_jwt(&subject).await.unwrap().access_token;
+    let header = jsonwebtoken::decode_header(&new_token).unwrap();
+    assert_eq!(header.kid.as_deref(), Some("new"));
+    assert!(rotating.verify_access_token(&old_token).await.is_ok());
+    assert!(rotating.verify_access_token(&legacy_token).await.is_ok());
+    assert!(rotating.verify_access_token(&new_token).await.is_ok());
+
+    // 旧秘钥停用后: 旧令牌失效
+    let after = crypto_service(vec![new_key()], Some("new"));
+    assert!(after.verify_access_token(&old_token).await.is_err());
+    assert!(after.verify_access_token(&legacy_token).await.is_err());
+    assert!(after.verify_access_token(&new_token).await.is_ok());
+
+    // 当前秘钥不存在 / 秘钥id重复
+    assert!(JwtKeyring::new(vec![old_key()], Some("new")).is_err());
+    assert!(JwtKeyring::new(vec![old_key(), old_key()], None).is_err());
 }
@@ -0,0 +1,212 @@
+use async_graphql::guard::Guard;
+use async_graphql::{Context, ErrorExtensions, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::common::error::errors::AppError;
+use crate::web::gql::current_user;
+use crate::CONFIGS;
+
+/// 管理员角色
+pub const ROLE_ADMIN: &str = "admin";
+/// 普通用户角色, 注册时默认分配
+pub const ROLE_USER: &str = "user";
+
+/// 查询其他用户信息
+pub const PERMISSION_USERS_READ: &str = "users:read";
+/// 管理用户角色
+pub const PERMISSION_ROLES_MANAGE: &str = "roles:manage";
+/// 管理用户账号
+pub const PERMISSION_USERS_MANAGE: &str = "users:manage";
+/// 查询审计日志
+pub const PERMISSION_AUDIT_READ: &str = "audit:read";
+/// 代登录其他用户
+pub const PERMISSION_USERS_IMPERSONATE: &str = "users:impersonate";
+
+/// 角色守卫, 要求当前用户拥有指定角色
+///
+/// ```text
+/// #[graphql(guard(RoleGuard(role = "ROLE_ADMIN")))]
+/// ```
+pub struct RoleGuard {
+    pub role: String,
+}
+
+#[async_trait::async_trait]
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let current_user = current_user(ctx)?;
+        if current_user.roles.contains(&self.role) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.extend())
+        }
+    }
+}
+
+/// 权限守卫, 要求当前用户的角色拥有指定权限
+///
+/// ```text
+/// #[graphql(guard(PermissionGuard(permission = "PERMISSION_USERS_READ")))]
+/// ```
+pub struct PermissionGuard {
+    pub permission: String,
+}
+
+#[async_trait::async_trait]
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let current_user = current_user(ctx)?;
+        if current_user.permissions.contains(&self.permission) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.extend())
+        }
+    }
+}
+
+/// 代登录守卫, 管理员代登录时不能调用, 避免借用被代登录用户的权限执行管理操作
+///
+/// ```text
+/// #[graphql(guard(and(NotImpersonatingGuard(), PermissionGuard(permission = "PERMISSION_ROLES_MANAGE"))))]
+/// ```
+pub struct NotImpersonatingGuard;
+
+#[async_trait::async_trait]
+impl Guard for NotImpersonatingGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        match current_user(ctx)?.impersonator {
+            Some(_) => Err(AppError::ImpersonationForbidden.extend()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 会话守卫, 要求使用登录会话认证, 个人访问令牌不能调用令牌/两步验证等账号安全相关的接口,
+/// 管理员代登录时也不能调用
+///
+/// ```text
+/// #[graphql(guard(SessionGuard()))]
+/// ```
+pub struct SessionGuard;
+
+#[async_trait::async_trait]
+impl Guard for SessionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let current_user = current_user(ctx)?;
+        if current_user.impersonator.is_some() {
+            return Err(AppError::ImpersonationForbidden.extend());
+        }
+        if current_user.claims().is_some() {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.extend())
+        }
+    }
+}
+
+/// 敏感操作守卫, 要求在有效期 (`auth.reauthentication_window`) 内登录或重新验证过密码,
+/// 超过有效期返回 `AppError::ReauthenticationRequired`, 需要先调用 reauthenticate
+///
+/// ```text
+/// #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+/// ```
+pub struct RecentAuthGuard;
+
+#[async_trait::async_trait]
+impl Guard for RecentAuthGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let claims = current_user(ctx)?
+            .claims()
+            .ok_or_else(|| AppError::Forbidden.extend())?;
+        let window = CONFIGS.auth.get_reauthentication_window();
+        if is_recent_auth(claims.auth_time, Utc::now(), window) {
+            Ok(())
+        } else {
+            Err(AppError::ReauthenticationRequired.extend())
+        }
+    }
+}
+
+/// 最近一次验证密码的时间 (时间戳, 秒) 是否在有效期内, 没有记录时视为已过期
+fn is_recent_auth(auth_time: Option<i64>, now: DateTime<Utc>, window: Duration) -> bool {
+    match auth_time {
+        Some(auth_time) => now.timestamp() - auth_time <= window.num_seconds(),
+        None => false,
+    }
+}
+
+#[test]
+fn test_role_and_permission_guard() {
+    crate::block_on_db(async {
+        use async_graphql::{EmptyMutation, EmptySubscription, Object, Request, Schema};
+
+        use crate::security::auth::{authenticate, ClientInfo};
+        use crate::service::auth::{AuthService, ExtAuthService};
+        use crate::service::users::create_test_user;
+
+        struct GuardQuery;
+
+        #[Object]
+        impl GuardQuery {
+            #[graphql(guard(RoleGuard(role = "ROLE_USER")))]
+            async fn user_role(&self) -> bool {
+                true
+            }
+
+            #[graphql(guard(RoleGuard(role = "ROLE_ADMIN")))]
+            async fn admin_role(&self) -> bool {
+                true
+            }
+
+            #[graphql(guard(PermissionGuard(permission = "PERMISSION_USERS_READ")))]
+            async fn users_read(&self) -> bool {
+                true
+            }
+        }
+
+        let schema = Schema::new(GuardQuery, EmptyMutation, EmptySubscription);
+        let user = create_test_user("guard-9Kx2-Vq7!").await;
+        let token = AuthService::issue_tokens(&user.id, &ClientInfo::default())
+            .await
+            .unwrap();
+        let execute = |query: &'static str| {
+            let schema = schema.clone();
+            let token = token.access_token.clone();
+            async move {
+                let current_user = authenticate(&token).await.unwrap();
+                schema
+                    .execute(Request::new(query).data(current_user))
+                    .await
+                    .errors
+                    .into_iter()
+                    .map(|error| error.message)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // 注册时默认分配普通用户角色, 没有管理员角色和查询其他用户的权限
+        assert!(execute("{ userRole }").await.is_empty());
+        let forbidden = vec![AppError::Forbidden.to_string()];
+        assert_eq!(execute("{ adminRole }").await, forbidden);
+        assert_eq!(execute("{ usersRead }").await, forbidden);
+
+        // 未登录
+        let response = schema.execute("{ userRole }").await;
+        assert_eq!(
+            response.errors[0].message,
+            AppError::Unauthenticated.to_string()
+        );
+    })
+}
+
+#[test]
+fn test_is_recent_auth() {
+    let now = Utc::now();
+    let window = Duration::minutes(10);
+    let ago = |minutes| Some((now - Duration::minutes(minutes)).timestamp());
+
+    assert!(is_recent_auth(ago(0), now, window));
+    assert!(is_recent_auth(ago(10), now, window));
+    assert!(!is_recent_auth(ago(11), now, window));
+    assert!(!is_recent_auth(None, now, window));
+}
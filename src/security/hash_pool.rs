@@ -0,0 +1,182 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::sync::{oneshot, Semaphore};
+
+use crate::common::error::errors::AppError;
+
+/// 提交到计算线程的任务
+type Job = Box<dyn FnOnce() + Send>;
+
+/// 密码哈希计算池
+///
+/// argon2 是 CPU 密集型计算, 放在专用的 `concurrency` 个线程中执行, 不占用处理其他请求的异步线程,
+/// 也不占用 tokio 共享的阻塞线程池 (`spawn_blocking`, 数据库驱动等也在使用).
+/// 同时进行的计算数量等于线程数量, 排队超过 `queue_timeout` 返回 `AppError::ServerBusy`.
+/// 许可随任务一起提交, 计算完成后才释放, 调用方取消 (客户端断开等) 后不能继续提交新任务.
+#[derive(Debug)]
+pub struct HashPool {
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+    jobs: Mutex<Sender<Job>>,
+}
+
+impl HashPool {
+    /// 创建计算池并启动计算线程, 计算池释放后线程随之退出
+    pub fn new(concurrency: usize, queue_timeout: Duration) -> Self {
+        let concurrency = concurrency.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..concurrency {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("hash-pool-{}", index))
+                .spawn(move || Self::work(&receiver))
+                .expect("启动密码哈希计算线程失败");
+        }
+        HashPool {
+            permits: Arc::new(Semaphore::new(concurrency)),
+            queue_timeout,
+            jobs: Mutex::new(sender),
+        }
+    }
+
+    /// 计算线程: 依次执行任务, 任务 panic 不会导致线程退出
+    fn work(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            let job = match receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            // panic 时结果的发送端被释放, 调用方会收到错误
+            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        }
+    }
+
+    /// 在计算线程中执行计算
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| AppError::ServerBusy)?
+            .context("密码哈希计算池已关闭")?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.jobs
+            .lock()
+            .unwrap()
+            .send(Box::new(move || {
+                let _ = sender.send(f());
+                drop(permit);
+            }))
+            .map_err(|_| anyhow::anyhow!("密码哈希计算池已关闭"))?;
+        receiver.await.context("密码哈希计算异常!")
+    }
+}
+
+/// 大量密码哈希计算时, 其他请求仍然可以及时响应
+///
+/// 结果受机器负载影响, 需要手动运行: `cargo test bench_hash_pool -- --ignored`
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[ignore]
+async fn bench_hash_pool_keeps_executor_responsive() {
+    use tokio::time::Instant;
+
+    let pool = Arc::new(HashPool::new(2, Duration::from_secs(60)));
+    let hashes = (0..8)
+        .map(|i| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                pool.run(move || {
+                    let salt = format!("bench-salt-{}", i);
+                    argon2::hash_encoded(b"password", salt.as_bytes(), &argon2::Config::default())
+                })
+                .await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // 模拟其他请求: 每次等待 5ms, 记录实际等待时间
+    let mut max_latency = Duration::default();
+    for _ in 0..20 {
+        let start = Instant::now();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        max_latency = max_latency.max(start.elapsed());
+    }
+
+    for hash in hashes {
+        assert!(hash.await.unwrap().unwrap().is_ok());
+    }
+    assert!(
+        max_latency < Duration::from_millis(100),
+        "其他请求的最大等待时间: {:?}",
+        max_latency
+    );
+}
+
+#[tokio::test]
+async fn test_hash_pool_busy() {
+    let pool = Arc::new(HashPool::new(1, Duration::from_millis(10)));
+    let (release, blocked) = mpsc::channel::<()>();
+    let busy = {
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.run(move || blocked.recv()).await })
+    };
+    // 等待唯一的计算线程被占用
+    while pool.permits.available_permits() > 0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    let error = pool.run(|| ()).await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AppError>(),
+        Some(AppError::ServerBusy)
+    ));
+
+    release.send(()).unwrap();
+    assert!(busy.await.unwrap().unwrap().is_ok());
+    assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_hash_pool_panic() {
+    let pool = HashPool::new(1, Duration::from_secs(5));
+    assert!(pool.run(|| panic!("hash panic")).await.is_err());
+    // 计算线程没有因为 panic 退出
+    assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_hash_pool_cancelled() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = HashPool::new(1, Duration::from_millis(10));
+    let started = Arc::new(AtomicUsize::new(0));
+    let (release, blocked) = mpsc::channel::<()>();
+    let blocked = Arc::new(Mutex::new(blocked));
+
+    // 调用方等待结果时被取消, 之后的调用方不能再提交任务
+    for _ in 0..5 {
+        let started = started.clone();
+        let blocked = blocked.clone();
+        let job = pool.run(move || {
+            started.fetch_add(1, Ordering::SeqCst);
+            let _ = blocked.lock().unwrap().recv();
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(20), job)
+            .await
+            .map_or(true, |result| result.is_err()));
+    }
+
+    // 唯一的计算线程按顺序执行, 这里的任务完成时之前提交的任务都已执行
+    drop(release);
+    assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use openssl::bn::BigNumContext;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+
+/// jwt 签名秘钥
+pub struct JwtKey {
+    /// 秘钥id, 签发时写入 jwt 头部的 `kid`
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+    /// 非对称秘钥的公钥, 对称秘钥不对外公开
+    pub jwk: Option<Jwk>,
+}
+
+impl fmt::Debug for JwtKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtKey")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl JwtKey {
+    /// HS256 对称秘钥
+    pub fn from_secret(kid: Option<String>, secret: &str) -> JwtKey {
+        JwtKey {
+            kid,
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret.as_bytes()),
+            decoding: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+        }
+    }
+
+    /// 从 PEM 格式的私钥加载非对称秘钥, 支持 RS256 / ES256 / EdDSA
+    pub fn from_private_pem(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        pem: &[u8],
+    ) -> Result<JwtKey> {
+        let pkey = PKey::private_key_from_pem(pem).context("解析私钥失败")?;
+        // jsonwebtoken 要求 EC / Ed25519 私钥为 PKCS#8 格式
+        let private_pem = pkey.private_key_to_pem_pkcs8()?;
+        let public_pem = pkey.public_key_to_pem()?;
+
+        let (encoding, decoding, params) = match (algorithm, pkey.id()) {
+            (Algorithm::RS256, Id::RSA) => (
+                EncodingKey::from_rsa_pem(&private_pem)?,
+                DecodingKey::from_rsa_pem(&public_pem)?,
+                rsa_params(&pkey)?,
+            ),
+            (Algorithm::ES256, Id::EC) => (
+                EncodingKey::from_ec_pem(&private_pem)?,
+                DecodingKey::from_ec_pem(&public_pem)?,
+                ec_params(&pkey)?,
+            ),
+            (Algorithm::EdDSA, Id::ED25519) => (
+                EncodingKey::from_ed_pem(&private_pem)?,
+                DecodingKey::from_ed_pem(&public_pem)?,
+                ed_params(&pkey)?,
+            ),
+            (algorithm, id) => bail!("私钥类型: [{:?}] 与签名算法: [{:?}] 不匹配", id, algorithm),
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(algorithm),
+                key_id: kid.clone(),
+                ..CommonParameters::default()
+            },
+            algorithm: params,
+        };
+
+        Ok(JwtKey {
+            kid,
+            algorithm,
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+        })
+    }
+}
+
+/// jwt 签名秘钥环
+///
+/// 使用当前秘钥签发令牌, 验证时按令牌头部的 `kid` 查找秘钥.
+/// 轮换秘钥时先加入新秘钥并切换为当前秘钥, 旧秘钥保留到其签发的令牌全部过期后再停用,
+/// 用户不会因为更换秘钥而被强制登出.
+#[derive(Debug)]
+pub struct JwtKeyring {
+    keys: Vec<JwtKey>,
+    active: usize,
+}
+
+impl JwtKeyring {
+    /// 只有一个秘钥的秘钥环
+    pub fn single(key: JwtKey) -> JwtKeyring {
+        JwtKeyring {
+            keys: vec![key],
+            active: 0,
+        }
+    }
+
+    /// `active_kid` 为签发令牌使用的秘钥id, 为空时使用第一个秘钥
+    pub fn new(keys: Vec<JwtKey>, active_kid: Option<&str>) -> Result<JwtKeyring> {
+        if keys.is_empty() {
+            bail!("至少需要一个可用的 jwt 秘钥");
+        }
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].iter().any(|other| other.kid == key.kid) {
+                bail!("jwt 秘钥id: [{:?}] 重复", key.kid);
+            }
+        }
+        let active = match active_kid {
+            Some(kid) => keys
+                .iter()
+                .position(|key| key.kid.as_deref() == Some(kid))
+                .context(format!("当前 jwt 秘钥id: [{}] 不存在或已停用", kid))?,
+            None => 0,
+        };
+        Ok(JwtKeyring { keys, active })
+    }
+
+    /// 签发令牌使用的秘钥
+    pub fn active(&self) -> &JwtKey {
+        &self.keys[self.active]
+    }
+
+    /// 验证令牌可用的秘钥, 令牌头部没有 `kid` 时 (秘钥轮换前签发的令牌) 尝试所有秘钥
+    pub fn find(&self, kid: Option<&str>) -> Vec<&JwtKey> {
+        match kid {
+            Some(kid) => self
+                .keys
+                .iter()
+                .filter(|key| key.kid.as_deref() == Some(kid))
+                .collect(),
+            None => self.keys.iter().collect(),
+        }
+    }
+
+    /// 所有非对称秘钥的公钥
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+/// base64url 编码 (不填充)
+fn b64(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn rsa_params(pkey: &PKey<Private>) -> Result<AlgorithmParameters> {
+    let rsa = pkey.rsa()?;
+    Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: b64(&rsa.n().to_vec()),
+        e: b64(&rsa.e().to_vec()),
+    }))
+}
+
+fn ec_params(pkey: &PKey<Private>) -> Result<AlgorithmParameters> {
+    let ec = pkey.ec_key()?;
+    let group = ec.group();
+    if group.curve_name() != Some(Nid::X9_62_PRIME256V1) {
+        bail!("ES256 只支持 P-256 曲线");
+    }
+    let mut ctx = BigNumContext::new()?;
+    let mut x = openssl::bn::BigNum::new()?;
+    let mut y = openssl::bn::BigNum::new()?;
+    ec.public_key()
+        .affine_coordinates(group, &mut x, &mut y, &mut ctx)?;
+    Ok(AlgorithmParameters::EllipticCurve(
+        EllipticCurveKeyParameters {
+            key_type: EllipticCurveKeyType::EC,
+            curve: EllipticCurve::P256,
+            x: b64(&x.to_vec_padded(32)?),
+            y: b64(&y.to_vec_padded(32)?),
+        },
+    ))
+}
+
+fn ed_params(pkey: &PKey<Private>) -> Result<AlgorithmParameters> {
+    Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: b64(&pkey.raw_public_key()?),
+    }))
+}
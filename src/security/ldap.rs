@@ -0,0 +1,208 @@
+use anyhow::{Context, Result};
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+
+use crate::config::configs::LdapConfig;
+
+/// 密码错误 (invalidCredentials)
+const RC_INVALID_CREDENTIALS: u32 = 49;
+
+/// 目录中的用户
+#[derive(Debug, Clone)]
+pub struct LdapUser {
+    pub dn: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+/// LDAP 目录, 先查询用户的 DN, 再使用用户的 DN 和密码绑定验证密码
+pub struct LdapDirectory {
+    pub config: LdapConfig,
+}
+
+impl LdapDirectory {
+    pub fn new(config: LdapConfig) -> LdapDirectory {
+        LdapDirectory { config }
+    }
+
+    /// 验证登录名和密码, 用户不存在/不唯一/密码错误时返回 None
+    pub async fn authenticate(&self, login: &str, password: &str) -> Result<Option<LdapUser>> {
+        // 空密码会被当作匿名绑定, 直接拒绝
+        if login.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+        // 目录无响应时不能一直占用登录请求
+        tokio::time::timeout(self.config.get_timeout(), async {
+            let mut ldap = self.connect().await?;
+            let result = self.bind_user(&mut ldap, login, password).await;
+            ldap.unbind().await.ok();
+            result
+        })
+        .await
+        .with_context(|| format!("LDAP 目录: [{}] 响应超时", self.config.url))?
+    }
+
+    async fn connect(&self) -> Result<Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(self.config.get_timeout())
+            .set_starttls(self.config.starttls);
+        let (conn, ldap) = LdapConnAsync::with_settings(settings, &self.config.url)
+            .await
+            .with_context(|| format!("连接 LDAP 目录: [{}] 失败", self.config.url))?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    async fn bind_user(
+        &self,
+        ldap: &mut Ldap,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<LdapUser>> {
+        if let Some(bind_dn) = &self.config.bind_dn {
+            let bind_password = self.config.bind_password.as_deref().unwrap_or_default();
+            ldap.simple_bind(bind_dn, bind_password)
+                .await?
+                .success()
+                .context("LDAP 查询账号绑定失败")?;
+        }
+
+        let filter = self
+            .config
+            .user_filter
+            .replace("{login}", &ldap_escape(login));
+        let attrs = vec![
+            self.config.username_attribute.as_str(),
+            self.config.email_attribute.as_str(),
+            self.config.name_attribute.as_str(),
+        ];
+        let (mut entries, _) = ldap
+            .search(&self.config.base_dn, Scope::Subtree, &filter, attrs)
+            .await?
+            .success()
+            .context("LDAP 查询用户失败")?;
+        if entries.len() != 1 {
+            if entries.len() > 1 {
+                log::warn!("LDAP 登录名: [{}] 匹配到多个用户", login);
+            }
+            return Ok(None);
+        }
+        let entry = SearchEntry::construct(entries.remove(0));
+
+        let result = ldap.simple_bind(&entry.dn, password).await?;
+        if result.rc == RC_INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        result.success().context("LDAP 用户绑定失败")?;
+
+        let attr = |name: &str| {
+            entry
+                .attrs
+                .get(name)
+                .and_then(|values| values.first())
+                .cloned()
+        };
+        let username = match attr(&self.config.username_attribute) {
+            Some(username) => username,
+            None => return Ok(None),
+        };
+        Ok(Some(LdapUser {
+            username,
+            email: attr(&self.config.email_attribute),
+            name: attr(&self.config.name_attribute),
+            dn: entry.dn,
+        }))
+    }
+}
+
+/// 需要本地 OpenLDAP (`docker/docker-compose.yaml` 中的 openldap),
+/// 运行: `cargo test test_ldap_directory -- --ignored`
+#[tokio::test]
+#[ignore]
+async fn test_ldap_directory() {
+    use std::collections::HashSet;
+
+    let url = std::env::var("LDAP_TEST_URL").unwrap_or_else(|_| "ldap://localhost:389".into());
+    let config = LdapConfig {
+        name: "test".into(),
+        url,
+        starttls: false,
+        bind_dn: Some("cn=admin,dc=example,dc=org".into()),
+        bind_password: Some("admin".into()),
+        base_dn: "ou=users,dc=example,dc=org".into(),
+        user_filter: "(&(objectClass=inetOrgPerson)(|(uid={login})(mail={login})))".into(),
+        username_attribute: "uid".into(),
+        email_attribute: "mail".into(),
+        name_attribute: "cn".into(),
+        timeout: None,
+        auto_provision: true,
+        link_by_email: false,
+    };
+    let directory = LdapDirectory::new(config.clone());
+
+    // 准备测试数据, 已存在时忽略
+    let mut ldap = directory.connect().await.unwrap();
+    ldap.simple_bind("cn=admin,dc=example,dc=org", "admin")
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    let set = |values: &[&'static str]| values.iter().copied().collect::<HashSet<_>>();
+    ldap.add(
+        "ou=users,dc=example,dc=org",
+        vec![
+            ("objectClass", set(&["organizationalUnit"])),
+            ("ou", set(&["users"])),
+        ],
+    )
+    .await
+    .unwrap();
+    ldap.add(
+        "uid=dave,ou=users,dc=example,dc=org",
+        vec![
+            ("objectClass", set(&["inetOrgPerson"])),
+            ("uid", set(&["dave"])),
+            ("cn", set(&["Dave"])),
+            ("sn", set(&["Dave"])),
+            ("mail", set(&["dave@example.org"])),
+            ("userPassword", set(&["dave-secret"])),
+        ],
+    )
+    .await
+    .unwrap();
+    ldap.unbind().await.unwrap();
+
+    let user = directory
+        .authenticate("dave", "dave-secret")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.dn, "uid=dave,ou=users,dc=example,dc=org");
+    assert_eq!(user.username, "dave");
+    assert_eq!(user.email.as_deref(), Some("dave@example.org"));
+    assert_eq!(user.name.as_deref(), Some("Dave"));
+    // 使用邮箱登录
+    assert!(directory
+        .authenticate("dave@example.org", "dave-secret")
+        .await
+        .unwrap()
+        .is_some());
+
+    assert!(directory
+        .authenticate("dave", "wrong")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(directory.authenticate("dave", "").await.unwrap().is_none());
+    assert!(directory
+        .authenticate("nobody", "dave-secret")
+        .await
+        .unwrap()
+        .is_none());
+    // 过滤器注入
+    assert!(directory
+        .authenticate("*", "dave-secret")
+        .await
+        .unwrap()
+        .is_none());
+}
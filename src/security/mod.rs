@@ -1 +1,13 @@
+pub mod auth;
+pub mod breach;
 pub mod crypto;
+pub mod guards;
+pub mod hash_pool;
+pub mod keys;
+pub mod ldap;
+pub mod oidc;
+pub mod password_policy;
+pub mod provider;
+pub mod revocation;
+pub mod throttle;
+pub mod totp;
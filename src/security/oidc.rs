@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::config::configs::OidcProviderConfig;
+
+/// 请求身份提供方的超时时间
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 身份提供方可以使用的签名算法, 不接受对称算法
+const ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// 身份提供方元数据 (OpenID Connect Discovery)
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// 令牌端点的响应, 只需要其中的 id_token
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// id_token 中使用到的 claims
+#[derive(Deserialize, Debug)]
+pub struct IdTokenClaims {
+    /// 用户在身份提供方的唯一标识
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+}
+
+/// OpenID Connect 客户端 (授权码模式 + PKCE)
+pub struct OidcClient {
+    pub config: OidcProviderConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<ProviderMetadata>>,
+    jwks: RwLock<Option<JwkSet>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcProviderConfig) -> Result<OidcClient> {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .context("创建 HTTP 客户端失败")?;
+        Ok(OidcClient {
+            config,
+            http,
+            metadata: RwLock::new(None),
+            jwks: RwLock::new(None),
+        })
+    }
+
+    /// 获取身份提供方元数据, 第一次获取后缓存
+    pub async fn metadata(&self) -> Result<ProviderMetadata> {
+        if let Some(metadata) = self.metadata.read().await.as_ref() {
+            return Ok(metadata.clone());
+        }
+        let issuer = self.config.issuer.trim_end_matches('/');
+        let url = format!("{}/.well-known/openid-configuration", issuer);
+        let metadata = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("获取身份提供方元数据: [{}] 失败", url))?
+            .json::<ProviderMetadata>()
+            .await
+            .context("解析身份提供方元数据失败")?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            bail!(
+                "身份提供方元数据中的签发人: [{}] 与配置: [{}] 不一致",
+                metadata.issuer,
+                self.config.issuer
+            );
+        }
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// 生成跳转到身份提供方的授权地址
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String> {
+        let metadata = self.metadata().await?;
+        let scope = self.config.scopes.join(" ");
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", scope.as_str()),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("授权端点地址格式错误")?;
+        Ok(url.into())
+    }
+
+    /// 使用授权码换取 id_token 并验证, `nonce` 必须与发起登录时的一致
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims> {
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &self.config.client_secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .context("请求令牌端点失败")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("令牌端点返回错误: [{}] {}", status, body);
+        }
+        let tokens = response
+            .json::<TokenResponse>()
+            .await
+            .context("解析令牌端点响应失败")?;
+
+        let claims = self.verify_id_token(&metadata, &tokens.id_token).await?;
+        match &claims.nonce {
+            Some(value) if openssl::memcmp::eq(value.as_bytes(), nonce.as_bytes()) => Ok(claims),
+            _ => bail!("id_token 中的 nonce 不匹配"),
+        }
+    }
+
+    /// 验证 id_token 的签名/签发人/受众/有效期
+    async fn verify_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> Result<IdTokenClaims> {
+        let header = decode_header(id_token).context("解析 id_token 头部失败")?;
+        if !ALGORITHMS.contains(&header.alg) {
+            bail!("不支持的 id_token 签名算法: [{:?}]", header.alg);
+        }
+        let key = self.decoding_key(metadata, header.kid.as_deref()).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        let data =
+            decode::<IdTokenClaims>(id_token, &key, &validation).context("验证 id_token 失败")?;
+        Ok(data.claims)
+    }
+
+    /// 根据 kid 查找公钥, 找不到时重新获取一次 JWKS (身份提供方可能轮换了秘钥)
+    async fn decoding_key(
+        &self,
+        metadata: &ProviderMetadata,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey> {
+        for refresh in [false, true] {
+            if refresh || self.jwks.read().await.is_none() {
+                let jwks = self
+                    .http
+                    .get(&metadata.jwks_uri)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .context("获取身份提供方公钥失败")?
+                    .json::<JwkSet>()
+                    .await
+                    .context("解析身份提供方公钥失败")?;
+                *self.jwks.write().await = Some(jwks);
+            }
+            let jwks = self.jwks.read().await;
+            let jwk = match (jwks.as_ref(), kid) {
+                (Some(jwks), Some(kid)) => jwks.find(kid),
+                // 没有 kid 时只接受唯一的公钥
+                (Some(jwks), None) if jwks.keys.len() == 1 => jwks.keys.first(),
+                _ => None,
+            };
+            if let Some(jwk) = jwk {
+                return DecodingKey::from_jwk(jwk).context("身份提供方公钥格式错误");
+            }
+        }
+        bail!("找不到 id_token 的公钥: [{:?}]", kid)
+    }
+}
+
+/// 所有配置的身份提供方
+#[derive(Default)]
+pub struct OidcProviders {
+    clients: HashMap<String, OidcClient>,
+}
+
+impl OidcProviders {
+    pub fn new(configs: &[OidcProviderConfig]) -> Result<OidcProviders> {
+        let mut clients = HashMap::new();
+        for config in configs {
+            let name = config.name.clone();
+            if clients
+                .insert(name.clone(), OidcClient::new(config.clone())?)
+                .is_some()
+            {
+                bail!("身份提供方名称: [{}] 重复", name);
+            }
+        }
+        Ok(OidcProviders { clients })
+    }
+
+    /// 根据名称获取身份提供方
+    pub fn get(&self, name: &str) -> Option<&OidcClient> {
+        self.clients.get(name)
+    }
+
+    /// 所有身份提供方的名称
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.clients.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+/// 生成随机字符串, 用作 PKCE 的 code_verifier 和 nonce
+pub fn generate_random() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// PKCE S256: code_challenge = BASE64URL(SHA256(code_verifier))
+pub fn code_challenge(code_verifier: &str) -> String {
+    let digest = openssl::sha::sha256(code_verifier.as_bytes());
+    base64::encode_config(digest, base64::URL_SAFE_NO_PAD)
+}
+
+#[test]
+fn test_pkce() {
+    // RFC 7636 附录 B
+    assert_eq!(
+        code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+    assert_eq!(generate_random().len(), 43);
+}
+
+#[tokio::test]
+async fn test_oidc_mock_idp() {
+    use crate::security::keys::JwtKey;
+    use jsonwebtoken::{encode, Header};
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    // 模拟身份提供方: 授权时记录 code_challenge 和 nonce, 令牌端点校验 code_verifier 后签发 id_token
+    let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+    let key = Arc::new(
+        JwtKey::from_private_pem(
+            Some("idp-1".into()),
+            Algorithm::RS256,
+            &rsa.private_key_to_pem().unwrap(),
+        )
+        .unwrap(),
+    );
+    let issuer = Arc::new(Mutex::new(String::new()));
+    let pending = Arc::new(Mutex::new(HashMap::<String, (String, String)>::new()));
+
+    let discovery = {
+        let issuer = issuer.clone();
+        warp::path!(".well-known" / "openid-configuration").map(move || {
+            let issuer = issuer.lock().unwrap().clone();
+            warp::reply::json(&serde_json::json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+            }))
+        })
+    };
+    let jwks = {
+        let key = key.clone();
+        warp::path!("jwks").map(move || {
+            warp::reply::json(&serde_json::json!({ "keys": [key.jwk.as_ref().unwrap()] }))
+        })
+    };
+    let token = {
+        let (key, issuer, pending) = (key.clone(), issuer.clone(), pending.clone());
+        warp::path!("token")
+            .and(warp::post())
+            .and(warp::body::form())
+            .map(move |form: HashMap<String, String>| {
+                let entry = pending.lock().unwrap().remove(&form["code"]);
+                let (challenge, nonce) = match entry {
+                    Some(entry) if form["client_id"] == "server" => entry,
+                    _ => {
+                        return warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({"error": "invalid_grant"})),
+                            warp::http::StatusCode::BAD_REQUEST,
+                        )
+                    }
+                };
+                if code_challenge(&form["code_verifier"]) != challenge {
+                    return warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"error": "invalid_grant"})),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    );
+                }
+                let now = chrono::Utc::now().timestamp();
+                let mut header = Header::new(Algorithm::RS256);
+                header.kid = key.kid.clone();
+                let id_token = encode(
+                    &header,
+                    &serde_json::json!({
+                        "iss": issuer.lock().unwrap().clone(),
+                        "aud": "server",
+                        "sub": "idp-user-1",
+                        "email": "alice@corp.example",
+                        "email_verified": true,
+                        "preferred_username": "alice",
+                        "nonce": nonce,
+                        "iat": now,
+                        "exp": now + 300,
+                    }),
+                    &key.encoding,
+                )
+                .unwrap();
+                warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({
+                        "access_token": "at",
+                        "token_type": "Bearer",
+                        "id_token": id_token,
+                    })),
+                    warp::http::StatusCode::OK,
+                )
+            })
+    };
+    let (addr, server) =
+        warp::serve(discovery.or(jwks).or(token)).bind_ephemeral(([127, 0, 0, 1], 0));
+    *issuer.lock().unwrap() = format!("http://{}", addr);
+    tokio::spawn(server);
+
+    let client = OidcClient::new(OidcProviderConfig {
+        name: "mock".into(),
+        issuer: format!("http://{}/", addr),
+        client_id: "server".into(),
+        client_secret: None,
+        redirect_uri: "http://localhost/callback".into(),
+        scopes: vec!["openid".into(), "email".into()],
+        auto_provision: true,
+        link_by_email: false,
+    })
+    .unwrap();
+
+    // 模拟用户在身份提供方登录并授权
+    let authorize = |code: &str, verifier: &str, nonce: &str| {
+        pending
+            .lock()
+            .unwrap()
+            .insert(code.into(), (code_challenge(verifier), nonce.into()));
+    };
+
+    let (verifier, nonce) = (generate_random(), generate_random());
+    let url = client
+        .authorization_url("state-1", &nonce, &code_challenge(&verifier))
+        .await
+        .unwrap();
+    let url = Url::parse(&url).unwrap();
+    assert_eq!(url.path(), "/authorize");
+    let params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+    assert_eq!(params["code_challenge"], code_challenge(&verifier));
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert_eq!(params["scope"], "openid email");
+    assert_eq!(params["state"], "state-1");
+
+    authorize("code-1", &verifier, &nonce);
+    let claims = client
+        .exchange_code("code-1", &verifier, &nonce)
+        .await
+        .unwrap();
+    assert_eq!(claims.sub, "idp-user-1");
+    assert_eq!(claims.email.as_deref(), Some("alice@corp.example"));
+    assert!(claims.email_verified);
+
+    // 授权码只能使用一次
+    assert!(client
+        .exchange_code("code-1", &verifier, &nonce)
+        .await
+        .is_err());
+    // code_verifier 不匹配
+    authorize("code-2", &verifier, &nonce);
+    assert!(client
+        .exchange_code("code-2", &generate_random(), &nonce)
+        .await
+        .is_err());
+    // nonce 不匹配
+    authorize("code-3", &verifier, &nonce);
+    assert!(client
+        .exchange_code("code-3", &verifier, &generate_random())
+        .await
+        .is_err());
+}
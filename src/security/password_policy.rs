@@ -0,0 +1,293 @@
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::common::error::errors::AppError;
+use crate::config::configs::{BreachedPasswordMode, PasswordPolicyConfig};
+use crate::{BREACHED_PASSWORDS, CONFIGS};
+
+/// 用户名/邮箱等个人信息参与检查的最小长度, 过短的片段容易误判
+const MIN_PERSONAL_LENGTH: usize = 3;
+
+/// 特殊字符 (ASCII 标点) 的数量
+const SYMBOL_POOL: f64 = 33.0;
+
+/// 非 ASCII 字符按较大的字符集估算
+const OTHER_POOL: f64 = 100.0;
+
+/// 不符合密码策略的规则
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PasswordViolation {
+    #[error("密码长度不能少于 {0} 个字符")]
+    TooShort(usize),
+
+    #[error("密码长度不能超过 {0} 个字符")]
+    TooLong(usize),
+
+    #[error("密码需要包含小写字母")]
+    MissingLowercase,
+
+    #[error("密码需要包含大写字母")]
+    MissingUppercase,
+
+    #[error("密码需要包含数字")]
+    MissingDigit,
+
+    #[error("密码需要包含特殊字符")]
+    MissingSymbol,
+
+    #[error("密码过于简单, 请避免重复或连续的字符")]
+    TooPredictable,
+
+    #[error("密码包含常见的弱密码单词")]
+    BannedWord,
+
+    #[error("密码不能包含用户名或邮箱")]
+    PersonalInfo,
+
+    #[error("密码已在数据泄露事件中出现, 请更换密码")]
+    Breached,
+}
+
+impl PasswordViolation {
+    /// 规则名称, 返回给客户端用于展示对应的提示
+    pub fn rule(&self) -> &'static str {
+        match self {
+            PasswordViolation::TooShort(_) => "minLength",
+            PasswordViolation::TooLong(_) => "maxLength",
+            PasswordViolation::MissingLowercase => "lowercase",
+            PasswordViolation::MissingUppercase => "uppercase",
+            PasswordViolation::MissingDigit => "digit",
+            PasswordViolation::MissingSymbol => "symbol",
+            PasswordViolation::TooPredictable => "entropy",
+            PasswordViolation::BannedWord => "bannedWord",
+            PasswordViolation::PersonalInfo => "personalInfo",
+            PasswordViolation::Breached => "breached",
+        }
+    }
+}
+
+/// 密码策略
+pub struct PasswordPolicy;
+
+impl PasswordPolicy {
+    /// 按配置的密码策略检查密码, `personal` 为用户名/邮箱等不能出现在密码中的信息 (第一个为用户名),
+    /// 不符合时返回 `AppError::WeakPassword`
+    pub async fn check(password: &str, personal: &[&str]) -> Result<()> {
+        let config = &CONFIGS.auth.password_policy;
+        let mut violations = check_password(config, password, personal);
+        if BREACHED_PASSWORDS.contains(password).await? {
+            match config.breached.mode {
+                BreachedPasswordMode::Warn => log::warn!(
+                    "用户: [{}] 使用了已泄露的密码",
+                    personal.first().unwrap_or(&"")
+                ),
+                BreachedPasswordMode::Reject => violations.push(PasswordViolation::Breached),
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::WeakPassword(violations).into())
+        }
+    }
+}
+
+/// 检查密码, 返回所有不符合的规则
+pub fn check_password(
+    config: &PasswordPolicyConfig,
+    password: &str,
+    personal: &[&str],
+) -> Vec<PasswordViolation> {
+    let mut violations = Vec::new();
+
+    let length = password.chars().count();
+    if length < config.min_length {
+        violations.push(PasswordViolation::TooShort(config.min_length));
+    }
+    if length > config.max_length {
+        violations.push(PasswordViolation::TooLong(config.max_length));
+    }
+
+    let classes = [
+        (
+            config.require_lowercase,
+            password.chars().any(|c| c.is_ascii_lowercase()),
+            PasswordViolation::MissingLowercase,
+        ),
+        (
+            config.require_uppercase,
+            password.chars().any(|c| c.is_ascii_uppercase()),
+            PasswordViolation::MissingUppercase,
+        ),
+        (
+            config.require_digit,
+            password.chars().any(|c| c.is_ascii_digit()),
+            PasswordViolation::MissingDigit,
+        ),
+        (
+            config.require_symbol,
+            password.chars().any(|c| !c.is_ascii_alphanumeric()),
+            PasswordViolation::MissingSymbol,
+        ),
+    ];
+    for (required, present, violation) in classes.iter() {
+        if *required && !*present {
+            violations.push(violation.clone());
+        }
+    }
+
+    if config.min_entropy > 0.0 && estimate_entropy(password) < config.min_entropy {
+        violations.push(PasswordViolation::TooPredictable);
+    }
+
+    let lowercase = password.to_lowercase();
+    let normalized = normalize(&lowercase);
+    let contains = |word: &str| {
+        let word = word.to_lowercase();
+        lowercase.contains(&word) || normalized.contains(&word)
+    };
+    if config
+        .banned_words
+        .iter()
+        .any(|word| !word.is_empty() && contains(word))
+    {
+        violations.push(PasswordViolation::BannedWord);
+    }
+    if personal_parts(personal)
+        .iter()
+        .any(|part| part.chars().count() >= MIN_PERSONAL_LENGTH && contains(part))
+    {
+        violations.push(PasswordViolation::PersonalInfo);
+    }
+
+    violations
+}
+
+/// 估算密码的熵 (位)
+///
+/// 按包含的字符类型确定字符集大小, 每个字符贡献 log2(字符集大小) 位,
+/// 与前一个字符相同或连续 (例如 `aa` / `ab` / `21`) 的字符只贡献 1 位.
+pub fn estimate_entropy(password: &str) -> f64 {
+    let mut pool = 0.0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26.0;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26.0;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10.0;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool += SYMBOL_POOL;
+    }
+    if !password.is_ascii() {
+        pool += OTHER_POOL;
+    }
+    if pool == 0.0 {
+        return 0.0;
+    }
+
+    let bits_per_char = f64::log2(pool);
+    let mut entropy = 0.0;
+    let mut previous: Option<char> = None;
+    for c in password.chars() {
+        let predictable = previous
+            .map(|previous| (c as i64 - previous as i64).abs() <= 1)
+            .unwrap_or(false);
+        entropy += if predictable { 1.0 } else { bits_per_char };
+        previous = Some(c);
+    }
+    entropy
+}
+
+/// 还原常见的字符替换, 例如 `p@ssw0rd` -> `password`
+fn normalize(password: &str) -> String {
+    password
+        .chars()
+        .map(|c| match c {
+            '@' | '4' => 'a',
+            '3' => 'e',
+            '1' | '!' => 'i',
+            '0' => 'o',
+            '$' | '5' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect()
+}
+
+/// 个人信息及邮箱的用户名部分
+fn personal_parts<'a>(personal: &[&'a str]) -> Vec<&'a str> {
+    let mut parts = Vec::with_capacity(personal.len() * 2);
+    for value in personal {
+        parts.push(*value);
+        if let Some((local, _)) = value.split_once('@') {
+            parts.push(local);
+        }
+    }
+    parts
+}
+
+#[test]
+fn test_estimate_entropy() {
+    assert_eq!(estimate_entropy(""), 0.0);
+    // 重复和连续的字符每个只有 1 位
+    assert!(estimate_entropy("aaaaaaaa") < 12.0);
+    assert!(estimate_entropy("abcdefgh") < 12.0);
+    assert!(estimate_entropy("87654321") < 11.0);
+    // 小写字母 + 数字, 没有规律
+    let entropy = estimate_entropy("zgmwqk4r");
+    assert!((entropy - 8.0 * f64::log2(36.0)).abs() < 1e-9);
+    assert!(estimate_entropy("Tr0ub4dor&3") > estimate_entropy("troubador"));
+}
+
+#[test]
+fn test_check_password() {
+    let config = PasswordPolicyConfig {
+        min_length: 8,
+        max_length: 16,
+        require_lowercase: true,
+        require_uppercase: true,
+        require_digit: true,
+        require_symbol: true,
+        min_entropy: 36.0,
+        banned_words: vec!["password".into()],
+        breached: Default::default(),
+    };
+    let personal = ["alice", "alice.w@example.org"];
+
+    assert!(check_password(&config, "Vq7#mKz2!pLx", &personal).is_empty());
+
+    assert_eq!(
+        check_password(&config, "abc", &personal),
+        vec![
+            PasswordViolation::TooShort(8),
+            PasswordViolation::MissingUppercase,
+            PasswordViolation::MissingDigit,
+            PasswordViolation::MissingSymbol,
+            PasswordViolation::TooPredictable,
+        ]
+    );
+    assert_eq!(
+        check_password(&config, "Vq7#mKz2!pLxVq7#mKz2!pLx", &personal),
+        vec![PasswordViolation::TooLong(16)]
+    );
+    // 常见的字符替换
+    assert_eq!(
+        check_password(&config, "Xy9#P@ssw0rd", &personal),
+        vec![PasswordViolation::BannedWord]
+    );
+    // 用户名和邮箱的用户名部分, 不区分大小写
+    assert_eq!(
+        check_password(&config, "Xy9#ALICEqz", &personal),
+        vec![PasswordViolation::PersonalInfo]
+    );
+    assert_eq!(
+        check_password(&config, "Qz8!alice.wK", &personal),
+        vec![PasswordViolation::PersonalInfo]
+    );
+}
@@ -0,0 +1,283 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+
+use crate::config::configs::LdapConfig;
+use crate::domain::users::Users;
+use crate::repository::external_identities::{
+    ExtExternalIdentitiesRepository, ExternalIdentitiesRepository,
+};
+use crate::security::ldap::LdapDirectory;
+use crate::service::external_identities::{
+    ExtExternalIdentitiesService, ExternalIdentitiesService, ExternalProfile, ProvisionPolicy,
+};
+use crate::service::users::{ExtUsersService, UsersService};
+use crate::{CRYPTO, EMAIL_REGEX};
+
+/// 本地用户表的身份验证方式名称
+pub const LOCAL_PROVIDER: &str = "local";
+
+/// 用户名密码登录的身份验证方式
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// 名称
+    fn name(&self) -> &str;
+
+    /// 验证登录名 (已转换为小写) 和密码, 通过时返回对应的本地用户, 不通过时返回 None
+    async fn authenticate(&self, login: &str, password: &str) -> Result<Option<Users>>;
+
+    /// 本地用户在该身份验证方式中的登录名, 用于重新验证密码, 没有对应的账号时返回 None
+    async fn login_of(&self, user: &Users) -> Result<Option<String>>;
+}
+
+/// 本地用户表, 使用 argon2 验证密码
+pub struct LocalProvider;
+
+impl LocalProvider {
+    /// 升级旧版本的密码哈希, 失败不影响本次登录
+    async fn rehash_password(users: &Users, password: &str) {
+        let result = match CRYPTO.generate_password_hash(password).await {
+            Ok(password_hash) => {
+                UsersService::update_password_hash(&users.id, &password_hash).await
+            }
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(_) => log::info!("用户: [{}] 密码哈希已升级", &users.username),
+            Err(error) => log::error!("用户: [{}] 密码哈希升级失败: {:#}", &users.username, error),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for LocalProvider {
+    fn name(&self) -> &str {
+        LOCAL_PROVIDER
+    }
+
+    async fn authenticate(&self, login: &str, password: &str) -> Result<Option<Users>> {
+        let users = if EMAIL_REGEX.is_match(login) {
+            UsersService::find_by_email(login).await?
+        } else {
+            UsersService::find_by_username(login).await?
+        };
+        let users = match users {
+            Some(users) => users,
+            None => {
+                // 用户不存在时同样验证一次密码哈希, 避免通过响应时间判断用户是否存在
+                CRYPTO.verify_dummy_password(password).await?;
+                return Ok(None);
+            }
+        };
+
+        if !CRYPTO
+            .verify_password(password, &users.password_hash)
+            .await?
+        {
+            return Ok(None);
+        }
+        if CRYPTO.needs_rehash(&users.password_hash) {
+            Self::rehash_password(&users, password).await;
+        }
+        Ok(Some(users))
+    }
+
+    async fn login_of(&self, user: &Users) -> Result<Option<String>> {
+        Ok(Some(user.username.clone()))
+    }
+}
+
+/// LDAP 目录, 验证通过后按目录中的用户名查找或创建本地用户
+pub struct LdapProvider {
+    directory: LdapDirectory,
+}
+
+#[async_trait]
+impl AuthProvider for LdapProvider {
+    fn name(&self) -> &str {
+        &self.directory.config.name
+    }
+
+    async fn authenticate(&self, login: &str, password: &str) -> Result<Option<Users>> {
+        let ldap_user = match self.directory.authenticate(login, password).await? {
+            Some(ldap_user) => ldap_user,
+            None => return Ok(None),
+        };
+        log::debug!("LDAP 用户: [{}] 验证通过", ldap_user.dn);
+
+        let config = &self.directory.config;
+        let profile = ExternalProfile {
+            provider: config.name.clone(),
+            subject: ldap_user.username.clone(),
+            username: Some(ldap_user.username),
+            email: ldap_user.email,
+            // 目录中的邮箱由管理员维护, 视为已验证
+            email_verified: true,
+            name: ldap_user.name,
+        };
+        let policy = ProvisionPolicy {
+            auto_provision: config.auto_provision,
+            link_by_email: config.link_by_email,
+        };
+        ExternalIdentitiesService::resolve_user(&profile, policy)
+            .await
+            .map(Some)
+    }
+
+    /// 自动创建的用户名冲突时会追加后缀, 使用绑定的目录账号 (目录中的用户名)
+    async fn login_of(&self, user: &Users) -> Result<Option<String>> {
+        let identity = ExternalIdentitiesRepository::find_by_user(&user.id, self.name()).await?;
+        Ok(identity.map(|identity| identity.subject))
+    }
+}
+
+/// 按配置顺序尝试的身份验证方式
+pub struct AuthProviders {
+    providers: Vec<Box<dyn AuthProvider>>,
+}
+
+impl AuthProviders {
+    pub fn new(names: &[String], ldap: &[LdapConfig]) -> Result<AuthProviders> {
+        if names.is_empty() {
+            bail!("至少需要配置一种身份验证方式");
+        }
+        let mut providers: Vec<Box<dyn AuthProvider>> = Vec::with_capacity(names.len());
+        for name in names {
+            if providers.iter().any(|provider| provider.name() == name) {
+                bail!("身份验证方式: [{}] 重复", name);
+            }
+            if name == LOCAL_PROVIDER {
+                providers.push(Box::new(LocalProvider));
+                continue;
+            }
+            match ldap.iter().find(|config| &config.name == name) {
+                Some(config) => providers.push(Box::new(LdapProvider {
+                    directory: LdapDirectory::new(config.clone()),
+                })),
+                None => bail!("身份验证方式: [{}] 未配置", name),
+            }
+        }
+        Ok(AuthProviders { providers })
+    }
+
+    /// 依次使用各个身份验证方式验证, 返回第一个验证通过的用户,
+    /// 某个方式出错 (例如 LDAP 目录不可用) 时继续尝试下一个, 全部未通过时返回第一个错误
+    pub async fn authenticate(&self, login: &str, password: &str) -> Result<Option<Users>> {
+        let mut first_error = None;
+        for provider in &self.providers {
+            match provider.authenticate(login, password).await {
+                Ok(Some(users)) => {
+                    log::info!(
+                        "用户: [{}] 通过: [{}] 验证",
+                        &users.username,
+                        provider.name()
+                    );
+                    return Ok(Some(users));
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    log::error!("身份验证方式: [{}] 出错: {:#}", provider.name(), error);
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(None),
+        }
+    }
+
+    /// 验证已登录用户的密码, 各个身份验证方式使用用户在其中的登录名,
+    /// 必须验证为同一个用户才算通过, 出错时的处理与 `authenticate` 相同
+    pub async fn verify(&self, user: &Users, password: &str) -> Result<bool> {
+        let mut first_error = None;
+        for provider in &self.providers {
+            let result = match provider.login_of(user).await {
+                Ok(Some(login)) => provider.authenticate(&login, password).await,
+                Ok(None) => continue,
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(Some(verified)) if verified.id == user.id => return Ok(true),
+                Ok(_) => {}
+                Err(error) => {
+                    log::error!("身份验证方式: [{}] 出错: {:#}", provider.name(), error);
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(false),
+        }
+    }
+
+    /// 用户的密码是否由外部目录管理 (在本地用户表以外的身份验证方式中有登录名),
+    /// 这些用户的本地密码哈希是随机值, 不能修改或重置, 否则目录中停用或修改密码后本地密码仍然有效
+    pub async fn is_external(&self, user: &Users) -> Result<bool> {
+        for provider in &self.providers {
+            if provider.name() != LOCAL_PROVIDER && provider.login_of(user).await?.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[test]
+fn test_login_of() {
+    crate::block_on_db(async {
+        use crate::service::users::create_test_user;
+
+        let user = create_test_user("provider-9Kx2").await;
+        let config = serde_json::from_value::<LdapConfig>(serde_json::json!({
+            "name": format!("ldap-{}", user.id),
+            "url": "ldap://localhost:389",
+            "base_dn": "ou=users,dc=example,dc=org",
+        }))
+        .unwrap();
+        let ldap = LdapProvider {
+            directory: LdapDirectory::new(config),
+        };
+
+        assert_eq!(
+            LocalProvider.login_of(&user).await.unwrap(),
+            Some(user.username.clone())
+        );
+        assert_eq!(ldap.login_of(&user).await.unwrap(), None);
+        // 目录用户名与本地用户名不同 (例如用户名冲突时追加了后缀)
+        ExternalIdentitiesRepository::create(&user.id, ldap.name(), "dave", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            ldap.login_of(&user).await.unwrap(),
+            Some("dave".to_string())
+        );
+    })
+}
+
+#[test]
+fn test_is_external() {
+    crate::block_on_db(async {
+        use crate::service::users::create_test_user;
+
+        let user = create_test_user("external-9Kx2").await;
+        let name = format!("ldap-{}", user.id);
+        let config = serde_json::from_value::<LdapConfig>(serde_json::json!({
+            "name": name,
+            "url": "ldap://localhost:389",
+            "base_dn": "ou=users,dc=example,dc=org",
+        }))
+        .unwrap();
+        let providers =
+            AuthProviders::new(&[LOCAL_PROVIDER.to_string(), name.clone()], &[config]).unwrap();
+
+        assert!(!providers.is_external(&user).await.unwrap());
+        ExternalIdentitiesRepository::create(&user.id, &name, "erin", None)
+            .await
+            .unwrap();
+        assert!(providers.is_external(&user).await.unwrap());
+        // 只使用本地用户表时不受目录绑定影响
+        let local = AuthProviders::new(&[LOCAL_PROVIDER.to_string()], &[]).unwrap();
+        assert!(!local.is_external(&user).await.unwrap());
+    })
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::repository::tokens::{ExtTokensRepository, TokensRepository};
+
+/// 已注销令牌存储
+///
+/// 以数据库为准, 内存中缓存未过期的记录, 每次认证请求只查询缓存.
+/// 定时清理会删除过期记录并重新加载缓存, 以便同步其他实例写入的记录.
+#[derive(Debug, Default)]
+pub struct RevocationStore {
+    cache: RwLock<HashMap<Uuid, DateTime<Utc>>>,
+}
+
+impl RevocationStore {
+    /// 从数据库加载未过期的记录
+    pub async fn load(&self) -> Result<()> {
+        let rows = TokensRepository::find_unexpired_revoked().await?;
+        let cache = rows
+            .into_iter()
+            .map(|row| (row.id, row.expires_at))
+            .collect::<HashMap<_, _>>();
+        log::debug!("加载已注销令牌: [{}] 条", cache.len());
+        *self.cache.write().unwrap() = cache;
+        Ok(())
+    }
+
+    /// 注销令牌, `id` 为令牌的 jti 或令牌族的 sid, 记录在 `expires_at` 之后失效
+    pub async fn revoke(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<()> {
+        TokensRepository::create_revoked(id, user_id, expires_at).await?;
+        self.insert(id, expires_at);
+        Ok(())
+    }
+
+    /// 使用一次性令牌, `id` 为令牌的 jti, 令牌已使用或已注销时返回 false
+    pub async fn consume(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        expires_at: &DateTime<Utc>,
+    ) -> Result<bool> {
+        let consumed = TokensRepository::create_revoked(id, user_id, expires_at).await?;
+        self.insert(id, expires_at);
+        Ok(consumed)
+    }
+
+    /// 检查令牌是否已注销
+    pub fn is_revoked(&self, id: &Uuid) -> bool {
+        match self.cache.read().unwrap().get(id) {
+            Some(expires_at) => *expires_at > Utc::now(),
+            None => false,
+        }
+    }
+
+    /// 清理过期记录并重新加载缓存
+    pub async fn purge(&self) -> Result<u64> {
+        let deleted = TokensRepository::delete_expired_revoked().await?;
+        self.load().await?;
+        Ok(deleted)
+    }
+
+    /// 定时清理过期记录
+    pub fn spawn_purge(&'static self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // 第一次 tick 会立即返回, 启动时已经加载过缓存
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match self.purge().await {
+                    Ok(deleted) => log::debug!("清理过期的已注销令牌: [{}] 条", deleted),
+                    Err(error) => log::error!("清理已注销令牌失败: {:#}", error),
+                }
+            }
+        });
+    }
+
+    fn insert(&self, id: &Uuid, expires_at: &DateTime<Utc>) {
+        self.cache.write().unwrap().insert(*id, *expires_at);
+    }
+}
+
+#[test]
+fn test_is_revoked() {
+    let store = RevocationStore::default();
+    let revoked = Uuid::new_v4();
+    let expired = Uuid::new_v4();
+    store.insert(&revoked, &(Utc::now() + chrono::Duration::minutes(1)));
+    store.insert(&expired, &(Utc::now() - chrono::Duration::minutes(1)));
+
+    assert!(store.is_revoked(&revoked));
+    assert!(!store.is_revoked(&expired));
+    assert!(!store.is_revoked(&Uuid::new_v4()));
+}
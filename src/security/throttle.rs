@@ -0,0 +1,170 @@
+use std::net::IpAddr;
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+
+use crate::common::error::errors::AppError;
+use crate::repository::throttles::{ExtThrottlesRepository, ThrottlesRepository};
+use crate::CONFIGS;
+
+/// 登录失败限制
+///
+/// 按账号和 IP 分别记录连续失败次数, 超过允许次数后锁定, 每多失败一次锁定时长翻倍.
+/// 账号不存在时按登录名计数, 避免通过是否锁定判断账号是否存在.
+pub struct LoginThrottle;
+
+impl LoginThrottle {
+    /// 账号的记录key
+    pub fn account_key(account: &str) -> String {
+        format!("account:{}", account)
+    }
+
+    /// IP 的记录key
+    pub fn ip_key(ip: &IpAddr) -> String {
+        format!("ip:{}", ip)
+    }
+
+    /// 检查是否锁定, 锁定时返回 `AppError::TooManyAttempts`
+    pub async fn check(keys: &[String]) -> Result<()> {
+        let now = Utc::now();
+        let locked_until = ThrottlesRepository::find_by_keys(keys)
+            .await?
+            .into_iter()
+            .filter_map(|row| row.locked_until)
+            .max();
+        match locked_until {
+            Some(locked_until) if locked_until > now => {
+                // 向上取整, 避免提示 0 秒
+                let retry_after = (locked_until - now).num_milliseconds().div_euclid(1000) + 1;
+                Err(AppError::TooManyAttempts(retry_after).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 记录一次失败, 超过允许次数时锁定
+    pub async fn record_failure(key: &str, max_failures: i32) -> Result<()> {
+        let config = &CONFIGS.auth.throttle;
+        let now = Utc::now();
+        let row = ThrottlesRepository::record_failure(key, &(now - config.get_window())).await?;
+        if let Some(lockout) = lockout_duration(
+            row.failures,
+            max_failures,
+            config.get_lockout(),
+            config.get_max_lockout(),
+        ) {
+            ThrottlesRepository::lock(key, &(now + lockout)).await?;
+            log::warn!(
+                "登录失败: [{}] 连续失败 [{}] 次, 锁定 [{}] 秒",
+                key,
+                row.failures,
+                lockout.num_seconds()
+            );
+        }
+        Ok(())
+    }
+
+    /// 记录一次登录失败, 账号和 IP 分别计数
+    pub async fn record_sign_in_failure(account_key: &str, ip_key: Option<&str>) -> Result<()> {
+        let config = &CONFIGS.auth.throttle;
+        Self::record_failure(account_key, config.max_account_failures).await?;
+        if let Some(ip_key) = ip_key {
+            Self::record_failure(ip_key, config.max_ip_failures).await?;
+        }
+        Ok(())
+    }
+
+    /// 记录一次登录成功, 清除账号的失败记录, IP 的失败次数只抵消一次,
+    /// 避免用自己的账号登录成功后清空同一 IP 对其他账号的失败记录
+    pub async fn record_sign_in_success(account_key: &str, ip_key: Option<&str>) -> Result<()> {
+        Self::clear(account_key).await?;
+        if let Some(ip_key) = ip_key {
+            ThrottlesRepository::decrement(ip_key).await?;
+        }
+        Ok(())
+    }
+
+    /// 检查并记录一次查询用户名是否存在的请求, 按 IP 计数, 每次查询都计入次数,
+    /// 无法获取 IP 的请求共用同一个计数
+    pub async fn record_existence_check(ip: Option<&IpAddr>) -> Result<()> {
+        let key = match ip {
+            Some(ip) => format!("exists:{}", ip),
+            None => "exists:unknown".to_string(),
+        };
+        Self::check(std::slice::from_ref(&key)).await?;
+        let max_requests = CONFIGS.auth.existence_check.get_max_requests();
+        Self::record_failure(&key, max_requests).await
+    }
+
+    /// 清除失败记录, 登录成功或管理员解锁时调用
+    pub async fn clear(key: &str) -> Result<bool> {
+        ThrottlesRepository::delete(key).await
+    }
+}
+
+/// 计算锁定时长, 第一次超过允许次数时锁定 `lockout`, 之后每次翻倍, 最长 `max_lockout`
+pub fn lockout_duration(
+    failures: i32,
+    max_failures: i32,
+    lockout: Duration,
+    max_lockout: Duration,
+) -> Option<Duration> {
+    if failures <= max_failures {
+        return None;
+    }
+    let exponent = (failures - max_failures - 1).min(30) as u32;
+    let lockout = lockout
+        .num_seconds()
+        .saturating_mul(2i64.saturating_pow(exponent));
+    Some(Duration::seconds(lockout).min(max_lockout))
+}
+
+#[test]
+fn test_lockout_duration() {
+    let lockout = Duration::seconds(30);
+    let max_lockout = Duration::hours(1);
+
+    assert_eq!(lockout_duration(5, 5, lockout, max_lockout), None);
+    assert_eq!(
+        lockout_duration(6, 5, lockout, max_lockout),
+        Some(Duration::seconds(30))
+    );
+    assert_eq!(
+        lockout_duration(7, 5, lockout, max_lockout),
+        Some(Duration::seconds(60))
+    );
+    assert_eq!(
+        lockout_duration(9, 5, lockout, max_lockout),
+        Some(Duration::seconds(240))
+    );
+    assert_eq!(
+        lockout_duration(100, 5, lockout, max_lockout),
+        Some(max_lockout)
+    );
+}
+
+#[test]
+fn test_record_sign_in_success() {
+    crate::block_on_db(async {
+        let id = uuid::Uuid::new_v4().to_string();
+        let account_key = LoginThrottle::account_key(&id);
+        let ip_key = format!("ip:test-{}", id);
+        for _ in 0..2 {
+            LoginThrottle::record_sign_in_failure(&account_key, Some(&ip_key))
+                .await
+                .unwrap();
+        }
+
+        // 登录成功清除账号的失败记录, IP 的失败次数只减少一次
+        LoginThrottle::record_sign_in_success(&account_key, Some(&ip_key))
+            .await
+            .unwrap();
+        let rows = ThrottlesRepository::find_by_keys(&[account_key, ip_key.clone()])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].key, ip_key);
+        assert_eq!(rows[0].failures, 1);
+        LoginThrottle::clear(&ip_key).await.unwrap();
+    })
+}
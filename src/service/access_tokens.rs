@@ -0,0 +1,85 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::domain::tokens::{CreatedAccessToken, NewAccessToken, PersonalAccessTokens};
+use crate::repository::access_tokens::{AccessTokensRepository, ExtAccessTokensRepository};
+use crate::security::auth::ACCESS_TOKEN_PREFIX;
+use crate::CRYPTO;
+
+pub struct AccessTokensService;
+
+#[async_trait]
+pub trait ExtAccessTokensService {
+    /// 创建个人访问令牌, 权限范围只能是用户当前拥有的权限 (`permissions`)
+    async fn create(
+        user_id: &Uuid,
+        permissions: &[String],
+        vm: &NewAccessToken,
+    ) -> Result<CreatedAccessToken>;
+
+    /// 查询用户未注销的个人访问令牌
+    async fn find_by_user(user_id: &Uuid) -> Result<Vec<PersonalAccessTokens>>;
+
+    /// 注销用户的个人访问令牌, 令牌不存在或不属于该用户时返回 false
+    async fn revoke(id: &Uuid, user_id: &Uuid) -> Result<bool>;
+
+    /// 验证个人访问令牌, 令牌无效/已注销/已过期时返回 None
+    async fn authenticate(token: &str) -> Result<Option<PersonalAccessTokens>>;
+}
+
+#[async_trait]
+impl ExtAccessTokensService for AccessTokensService {
+    async fn create(
+        user_id: &Uuid,
+        permissions: &[String],
+        vm: &NewAccessToken,
+    ) -> Result<CreatedAccessToken> {
+        if let Some(scope) = vm.scopes.iter().find(|scope| !permissions.contains(scope)) {
+            return Err(AppError::InvalidScope(scope.clone()).into());
+        }
+        if vm
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(AppError::RequestParameterError.into());
+        }
+        let mut scopes = vm.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+
+        let token = format!("{}{}", ACCESS_TOKEN_PREFIX, CRYPTO.generate_token());
+        let access_token = AccessTokensRepository::create(
+            user_id,
+            &vm.name,
+            &CRYPTO.hash_token(&token)?,
+            &scopes,
+            vm.expires_at.as_ref(),
+        )
+        .await?;
+        log::info!(
+            "用户: [{}] 创建了个人访问令牌: [{}]",
+            user_id,
+            access_token.id
+        );
+
+        Ok(CreatedAccessToken {
+            token,
+            access_token,
+        })
+    }
+
+    async fn find_by_user(user_id: &Uuid) -> Result<Vec<PersonalAccessTokens>> {
+        AccessTokensRepository::find_by_user(user_id).await
+    }
+
+    async fn revoke(id: &Uuid, user_id: &Uuid) -> Result<bool> {
+        AccessTokensRepository::revoke(id, user_id).await
+    }
+
+    async fn authenticate(token: &str) -> Result<Option<PersonalAccessTokens>> {
+        AccessTokensRepository::use_token(&CRYPTO.hash_token(token)?).await
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::domain::audit::{AuditEventFilter, AuditEventPage, NewAuditEvent, PageVM};
+use crate::repository::audit_events::{AuditEventsRepository, ExtAuditEventsRepository};
+
+pub struct AuditService;
+
+#[async_trait]
+pub trait ExtAuditService {
+    /// 记录审计事件, 失败时只输出日志, 不影响业务
+    async fn record(event: NewAuditEvent);
+
+    /// 按条件分页查询审计事件
+    async fn find_page(filter: &AuditEventFilter, page: &PageVM) -> Result<AuditEventPage>;
+}
+
+#[async_trait]
+impl ExtAuditService for AuditService {
+    async fn record(event: NewAuditEvent) {
+        if let Err(error) = AuditEventsRepository::create(&event).await {
+            log::error!("保存审计事件: {:?} 失败: {:#}", event, error);
+        }
+    }
+
+    async fn find_page(filter: &AuditEventFilter, page: &PageVM) -> Result<AuditEventPage> {
+        let items = AuditEventsRepository::find_page(filter, page.size, page.offset()).await?;
+        let total = AuditEventsRepository::count(filter).await?;
+        Ok(AuditEventPage {
+            items,
+            total,
+            page: page.page,
+            size: page.size,
+        })
+    }
+}
+
+#[test]
+fn test_audit_events_append_only() {
+    crate::block_on_db(async {
+        use crate::domain::audit::AuditAction;
+        use crate::security::auth::ClientInfo;
+
+        let marker = uuid::Uuid::new_v4().to_string();
+        AuditService::record(
+            NewAuditEvent::new(AuditAction::SignIn, &ClientInfo::default())
+                .metadata("test", marker.clone()),
+        )
+        .await;
+        let count = || async {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT count(*) FROM audit_events WHERE metadata ->> 'test' = $1",
+            )
+            .bind(&marker)
+            .fetch_one(&*crate::POOL)
+            .await
+            .unwrap()
+        };
+        assert_eq!(count().await, 1);
+
+        // 触发器拒绝修改和删除
+        let updated = sqlx::query(
+            "UPDATE audit_events SET outcome = 'failure' WHERE metadata ->> 'test' = $1",
+        )
+        .bind(&marker)
+        .execute(&*crate::POOL)
+        .await;
+        assert!(updated.is_err());
+        let deleted = sqlx::query("DELETE FROM audit_events WHERE metadata ->> 'test' = $1")
+            .bind(&marker)
+            .execute(&*crate::POOL)
+            .await;
+        assert!(deleted.is_err());
+        assert_eq!(count().await, 1);
+    })
+}
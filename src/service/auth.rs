@@ -0,0 +1,372 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::domain::audit::{AuditAction, NewAuditEvent};
+use crate::domain::mfa::{MfaChallenge, SignInResult};
+use crate::domain::tokens::Sessions;
+use crate::domain::users::{ImpersonationToken, Users, UsersToken};
+use crate::repository::roles::{ExtRolesRepository, RolesRepository};
+use crate::repository::tokens::{ExtTokensRepository, TokensRepository};
+use crate::security::auth::ClientInfo;
+use crate::security::crypto::{Claims, TokenSubject};
+use crate::security::guards::ROLE_ADMIN;
+use crate::security::throttle::LoginThrottle;
+use crate::service::audit::{AuditService, ExtAuditService};
+use crate::service::mfa::{ExtMfaService, MfaService};
+use crate::service::users::{ExtUsersService, UsersService};
+use crate::{AUTH_PROVIDERS, CONFIGS, CRYPTO, REVOCATIONS};
+
+pub struct AuthService;
+
+#[async_trait]
+pub trait ExtAuthService {
+    /// 用户身份验证通过后登录, 启用两步验证时返回两步验证令牌, 用户已停用时返回 `AppError::AccountDisabled`
+    async fn sign_in(user_id: &Uuid, client: &ClientInfo) -> Result<SignInResult>;
+
+    /// 为用户签发令牌, 每次调用都会创建新的令牌族 (会话)
+    async fn issue_tokens(user_id: &Uuid, client: &ClientInfo) -> Result<UsersToken>;
+
+    /// 使用刷新令牌换取新的令牌, 旧的刷新令牌随即失效
+    async fn refresh_tokens(refresh_token: &str, client: &ClientInfo) -> Result<UsersToken>;
+
+    /// 查询用户的有效会话, `current_sid` 为当前请求所在的会话
+    async fn find_sessions(user_id: &Uuid, current_sid: &Uuid) -> Result<Vec<Sessions>>;
+
+    /// 注销用户的指定会话, 会话不存在或不属于该用户时返回 false
+    async fn revoke_session(user_id: &Uuid, session_id: &Uuid) -> Result<bool>;
+
+    /// 退出登录, 注销当前令牌及其所在令牌族
+    async fn logout(claims: &Claims) -> Result<()>;
+
+    /// 退出所有设备, 注销用户的全部令牌族
+    async fn logout_everywhere(user_id: &Uuid) -> Result<()>;
+
+    /// 验证已登录用户的密码, 依次使用配置的身份验证方式, 与登录共用账号的登录失败限制
+    async fn verify_password(user: &Users, password: &str) -> Result<()>;
+
+    /// 重新验证密码, 在当前令牌族下签发新的令牌, 新令牌可以在有效期内执行敏感操作
+    async fn reauthenticate(user: &Users, claims: &Claims, password: &str) -> Result<UsersToken>;
+
+    /// 管理员代登录指定用户, 不能代登录自己/已停用的用户/其他管理员
+    async fn impersonate(actor_id: &Uuid, user_id: &Uuid) -> Result<ImpersonationToken>;
+}
+
+impl AuthService {
+    /// 检查用户是否有效, 已停用的用户不能登录或刷新令牌
+    async fn check_active(user_id: &Uuid) -> Result<()> {
+        match UsersService::find_by_id(user_id).await? {
+            Some(user) if user.active => Ok(()),
+            _ => Err(AppError::AccountDisabled.into()),
+        }
+    }
+
+    /// 注销令牌族, 令牌族内已签发的访问令牌 (包括代登录令牌) 最迟在其有效期后失效
+    async fn revoke_family(user_id: &Uuid, family_id: &Uuid) -> Result<()> {
+        TokensRepository::revoke_family(family_id).await?;
+        let expires_at = Utc::now() + CRYPTO.max_access_expires();
+        REVOCATIONS.revoke(family_id, user_id, &expires_at).await
+    }
+
+    /// 轮换刷新令牌并在原令牌族下签发新的令牌
+    async fn rotate_refresh_token(
+        user_id: &Uuid,
+        claims: &Claims,
+        client: &ClientInfo,
+    ) -> Result<UsersToken> {
+        Self::check_active(user_id).await?;
+
+        // 轮换成功说明这是该令牌第一次被使用
+        if let Some(rotated) = TokensRepository::rotate_refresh_token(&claims.jti).await? {
+            let ip = client.ip.map(|ip| ip.to_string());
+            TokensRepository::touch_family(
+                &rotated.family_id,
+                client.user_agent.as_deref(),
+                ip.as_deref(),
+            )
+            .await?;
+            // 刷新不会延长敏感操作的有效期
+            let auth_time = claims
+                .auth_time
+                .map(|auth_time| Utc.timestamp(auth_time, 0));
+            return Self::issue_in_family(user_id, &rotated.family_id, auth_time).await;
+        }
+
+        // 已经轮换过的令牌再次出现, 说明令牌可能被盗用, 注销整个令牌族
+        match TokensRepository::find_refresh_token(&claims.jti).await? {
+            Some(token) if token.rotated_at.is_some() => {
+                Self::revoke_family(user_id, &token.family_id).await?;
+                log::warn!(
+                    "用户: [{}] 的刷新令牌: [{}] 被重复使用, 已注销令牌族: [{}]",
+                    user_id,
+                    token.id,
+                    token.family_id
+                );
+                Err(AppError::RefreshTokenReused.into())
+            }
+            _ => Err(AppError::InvalidRefreshToken.into()),
+        }
+    }
+
+    /// 在令牌族下签发令牌并保存刷新令牌, 每次签发都会重新读取用户角色,
+    /// `auth_time` 为最近一次验证密码的时间
+    async fn issue_in_family(
+        user_id: &Uuid,
+        family_id: &Uuid,
+        auth_time: Option<DateTime<Utc>>,
+    ) -> Result<UsersToken> {
+        let subject = TokenSubject {
+            user_id: *user_id,
+            sid: *family_id,
+            roles: RolesRepository::find_names_by_user(user_id).await?,
+            auth_time,
+        };
+        let pair = CRYPTO.generate_jwt(&subject).await?;
+
+        TokensRepository::create_refresh_token(
+            &pair.refresh_jti,
+            family_id,
+            &pair.refresh_expires_at,
+        )
+        .await?;
+
+        Ok(UsersToken {
+            access_token: pair.access_token,
+            refash_token: pair.refresh_token,
+            expires: pair.expires.num_seconds(),
+        })
+    }
+}
+
+#[async_trait]
+impl ExtAuthService for AuthService {
+    async fn sign_in(user_id: &Uuid, client: &ClientInfo) -> Result<SignInResult> {
+        Self::check_active(user_id).await?;
+        if MfaService::is_enabled(user_id).await? {
+            let (mfa_token, expires) = CRYPTO.generate_mfa_token(user_id).await?;
+            return Ok(SignInResult::MfaChallenge(MfaChallenge {
+                mfa_token,
+                expires: expires.num_seconds(),
+            }));
+        }
+        Ok(SignInResult::Token(
+            Self::issue_tokens(user_id, client).await?,
+        ))
+    }
+
+    async fn issue_tokens(user_id: &Uuid, client: &ClientInfo) -> Result<UsersToken> {
+        Self::check_active(user_id).await?;
+        let ip = client.ip.map(|ip| ip.to_string());
+        let family = TokensRepository::create_family(
+            &Uuid::new_v4(),
+            user_id,
+            client.user_agent.as_deref(),
+            ip.as_deref(),
+        )
+        .await?;
+        Self::issue_in_family(user_id, &family.id, Some(Utc::now())).await
+    }
+
+    async fn refresh_tokens(refresh_token: &str, client: &ClientInfo) -> Result<UsersToken> {
+        let claims = match CRYPTO.verify_refresh_token(refresh_token).await {
+            Ok(data) => data.claims,
+            Err(error) => {
+                return Err(match error.downcast::<AppError>() {
+                    Ok(error) => error.into(),
+                    Err(_) => AppError::InvalidRefreshToken.into(),
+                })
+            }
+        };
+        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AppError::InvalidRefreshToken)?;
+
+        let result = Self::rotate_refresh_token(&user_id, &claims, client).await;
+        AuditService::record(
+            NewAuditEvent::new(AuditAction::TokenRefresh, client)
+                .actor(&user_id)
+                .subject(&user_id)
+                .result(&result),
+        )
+        .await;
+        result
+    }
+
+    async fn find_sessions(user_id: &Uuid, current_sid: &Uuid) -> Result<Vec<Sessions>> {
+        let families = TokensRepository::find_active_families_by_user(user_id).await?;
+        Ok(families
+            .into_iter()
+            .map(|family| Sessions::new(family, current_sid))
+            .collect())
+    }
+
+    async fn revoke_session(user_id: &Uuid, session_id: &Uuid) -> Result<bool> {
+        if TokensRepository::revoke_family_of_user(session_id, user_id).await? == 0 {
+            return Ok(false);
+        }
+        let expires_at = Utc::now() + CRYPTO.max_access_expires();
+        REVOCATIONS.revoke(session_id, user_id, &expires_at).await?;
+        log::info!("用户: [{}] 注销了会话: [{}]", user_id, session_id);
+        Ok(true)
+    }
+
+    async fn logout(claims: &Claims) -> Result<()> {
+        let user_id = Uuid::parse_str(&claims.sub)?;
+        let expires_at = Utc.timestamp(claims.exp, 0);
+        REVOCATIONS
+            .revoke(&claims.jti, &user_id, &expires_at)
+            .await?;
+        Self::revoke_family(&user_id, &claims.sid).await
+    }
+
+    async fn logout_everywhere(user_id: &Uuid) -> Result<()> {
+        let family_ids = TokensRepository::revoke_families_by_user(user_id).await?;
+        let expires_at = Utc::now() + CRYPTO.max_access_expires();
+        for family_id in &family_ids {
+            REVOCATIONS.revoke(family_id, user_id, &expires_at).await?;
+        }
+        log::info!(
+            "用户: [{}] 退出所有设备, 注销令牌族: [{}] 个",
+            user_id,
+            family_ids.len()
+        );
+        Ok(())
+    }
+
+    async fn verify_password(user: &Users, password: &str) -> Result<()> {
+        let account_key = LoginThrottle::account_key(&user.id.to_string());
+        LoginThrottle::check(std::slice::from_ref(&account_key)).await?;
+        let verify = AUTH_PROVIDERS.verify(user, password).await;
+        if !matches!(verify, Ok(true)) {
+            let max_failures = CONFIGS.auth.throttle.max_account_failures;
+            LoginThrottle::record_failure(&account_key, max_failures).await?;
+            verify?;
+            return Err(AppError::UsernameOrPasswordError.into());
+        }
+        LoginThrottle::clear(&account_key).await?;
+        Ok(())
+    }
+
+    async fn reauthenticate(user: &Users, claims: &Claims, password: &str) -> Result<UsersToken> {
+        Self::verify_password(user, password).await?;
+        log::info!("用户: [{}] 重新验证了密码", &user.username);
+        Self::issue_in_family(&user.id, &claims.sid, Some(Utc::now())).await
+    }
+
+    async fn impersonate(actor_id: &Uuid, user_id: &Uuid) -> Result<ImpersonationToken> {
+        if actor_id == user_id {
+            return Err(AppError::CannotImpersonate.into());
+        }
+        let user = match UsersService::find_by_id(user_id).await? {
+            Some(user) if user.active => user,
+            _ => return Err(AppError::CannotImpersonate.into()),
+        };
+        // 代登录其他管理员相当于借用其权限
+        let roles = RolesRepository::find_names_by_user(user_id).await?;
+        if roles.iter().any(|role| role == ROLE_ADMIN) {
+            return Err(AppError::CannotImpersonate.into());
+        }
+
+        // 代登录令牌不属于任何会话, 使用新的令牌族标识以便退出登录时注销
+        let subject = TokenSubject {
+            user_id: *user_id,
+            sid: Uuid::new_v4(),
+            roles,
+            auth_time: None,
+        };
+        let (access_token, expires) = CRYPTO
+            .generate_impersonation_token(&subject, actor_id)
+            .await?;
+        log::info!("管理员: [{}] 代登录用户: [{}]", actor_id, &user.username);
+        Ok(ImpersonationToken {
+            access_token,
+            expires: expires.num_seconds(),
+            user,
+        })
+    }
+}
+
+#[test]
+fn test_refresh_token_rotation() {
+    crate::block_on_db(async {
+        use crate::common::error::errors::is_app_error;
+        use crate::domain::audit::{AuditEventFilter, OUTCOME_FAILURE};
+        use crate::repository::audit_events::{AuditEventsRepository, ExtAuditEventsRepository};
+        use crate::service::users::{create_test_user, deactivate_test_user};
+
+        let user = create_test_user("rotation-9Kx2").await;
+        let client = ClientInfo::default();
+        let first = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let second = AuthService::refresh_tokens(&first.refash_token, &client)
+            .await
+            .unwrap();
+        let third = AuthService::refresh_tokens(&second.refash_token, &client)
+            .await
+            .unwrap();
+
+        // 已经轮换过的刷新令牌再次使用, 注销整个令牌族
+        let reused = AuthService::refresh_tokens(&first.refash_token, &client).await;
+        assert!(is_app_error(&reused, AppError::RefreshTokenReused));
+        let revoked = AuthService::refresh_tokens(&third.refash_token, &client).await;
+        assert!(is_app_error(&revoked, AppError::InvalidRefreshToken));
+
+        // 每次刷新都记录审计事件
+        let failures = AuditEventsRepository::count(&AuditEventFilter {
+            action: Some(AuditAction::TokenRefresh.as_str().to_string()),
+            outcome: Some(OUTCOME_FAILURE.to_string()),
+            subject_id: Some(user.id),
+            ..AuditEventFilter::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(failures, 2);
+
+        // 停用的用户不能登录或刷新令牌
+        let session = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        deactivate_test_user(&user.id).await;
+        let refreshed = AuthService::refresh_tokens(&session.refash_token, &client).await;
+        assert!(is_app_error(&refreshed, AppError::AccountDisabled));
+        let signed_in = AuthService::sign_in(&user.id, &client).await;
+        assert!(is_app_error(&signed_in, AppError::AccountDisabled));
+    })
+}
+
+#[test]
+fn test_revoked_access_tokens() {
+    crate::block_on_db(async {
+        use crate::security::auth::authenticate;
+        use crate::service::users::create_test_user;
+
+        let user = create_test_user("revocation-9Kx2").await;
+        let client = ClientInfo::default();
+
+        // 刷新令牌被重复使用后, 令牌族内已签发的访问令牌 (包括轮换后的) 全部失效
+        let first = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let second = AuthService::refresh_tokens(&first.refash_token, &client)
+            .await
+            .unwrap();
+        assert!(authenticate(&second.access_token).await.is_ok());
+        assert!(AuthService::refresh_tokens(&first.refash_token, &client)
+            .await
+            .is_err());
+        assert!(authenticate(&first.access_token).await.is_err());
+        assert!(authenticate(&second.access_token).await.is_err());
+
+        // 退出登录注销当前令牌族, 其他会话不受影响
+        let current = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let other = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let current_user = authenticate(&current.access_token).await.unwrap();
+        AuthService::logout(current_user.claims().unwrap())
+            .await
+            .unwrap();
+        assert!(authenticate(&current.access_token).await.is_err());
+        assert!(AuthService::refresh_tokens(&current.refash_token, &client)
+            .await
+            .is_err());
+        assert!(authenticate(&other.access_token).await.is_ok());
+
+        // 退出所有设备
+        AuthService::logout_everywhere(&user.id).await.unwrap();
+        assert!(authenticate(&other.access_token).await.is_err());
+    })
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::common::mail::mailer::Mail;
+use crate::domain::users::Users;
+use crate::repository::email_verifications::{
+    EmailVerificationsRepository, ExtEmailVerificationsRepository,
+};
+use crate::repository::users::{ExtUsersRepository, UsersRepository};
+use crate::{CONFIGS, CRYPTO, MAILER};
+
+pub struct EmailVerificationService;
+
+#[async_trait]
+pub trait ExtEmailVerificationService {
+    /// 发送邮箱验证邮件, 之前发送的验证链接随即失效
+    async fn send_verification(user: &Users) -> Result<()>;
+
+    /// 使用邮箱验证令牌验证邮箱, 返回用户id
+    async fn verify_email(token: &str) -> Result<Uuid>;
+
+    /// 重新发送邮箱验证邮件, 邮箱不存在或已验证时什么也不做, 避免泄露邮箱是否注册
+    async fn resend(email: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl ExtEmailVerificationService for EmailVerificationService {
+    async fn send_verification(user: &Users) -> Result<()> {
+        EmailVerificationsRepository::delete_unused_by_user(&user.id).await?;
+        let token = CRYPTO.generate_token();
+        let expires = CONFIGS.auth.get_email_verification_expires();
+        EmailVerificationsRepository::create(
+            &user.id,
+            &user.email,
+            &CRYPTO.hash_token(&token)?,
+            &(Utc::now() + expires),
+        )
+        .await?;
+
+        let url = CONFIGS
+            .auth
+            .email_verification_url
+            .replace("{token}", &token);
+        MAILER
+            .send(Mail {
+                to: user.email.clone(),
+                subject: "验证邮箱".to_string(),
+                body: format!(
+                    "{} 您好:\n\n请在 {} 小时内打开以下链接验证邮箱:\n{}\n\n如果不是您本人操作, 请忽略此邮件.",
+                    user.nickname,
+                    expires.num_hours(),
+                    url
+                ),
+            })
+            .await
+    }
+
+    async fn verify_email(token: &str) -> Result<Uuid> {
+        let user_id = EmailVerificationsRepository::consume(&CRYPTO.hash_token(token)?)
+            .await?
+            .ok_or(AppError::InvalidEmailVerificationToken)?;
+        log::info!("用户: [{}] 验证了邮箱", user_id);
+        Ok(user_id)
+    }
+
+    async fn resend(email: &str) -> Result<()> {
+        match UsersRepository::find_by_email(email).await? {
+            Some(user) if user.active && !user.email_verified => {
+                Self::send_verification(&user).await
+            }
+            _ => {
+                log::info!("重新发送验证邮件: 邮箱 [{}] 不存在或无需验证", email);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::Rng;
+
+use crate::common::error::errors::AppError;
+use crate::domain::users::{NewUser, Users};
+use crate::repository::external_identities::{
+    ExtExternalIdentitiesRepository, ExternalIdentitiesRepository,
+};
+use crate::security::auth::username_hint;
+use crate::service::users::{ExtUsersService, UsersService};
+use crate::CRYPTO;
+
+/// 生成可用用户名的尝试次数
+const USERNAME_ATTEMPTS: usize = 5;
+
+/// 外部身份提供方 (OpenID Connect / LDAP) 返回的账号信息
+#[derive(Debug, Clone)]
+pub struct ExternalProfile {
+    /// 身份提供方名称
+    pub provider: String,
+    /// 用户在身份提供方的唯一标识
+    pub subject: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    /// 身份提供方是否已验证邮箱
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// 外部账号绑定和自动创建用户的策略
+#[derive(Debug, Clone, Copy)]
+pub struct ProvisionPolicy {
+    /// 首次登录时是否自动创建用户
+    pub auto_provision: bool,
+    /// 是否按已验证的邮箱绑定已有用户
+    pub link_by_email: bool,
+}
+
+pub struct ExternalIdentitiesService;
+
+#[async_trait]
+pub trait ExtExternalIdentitiesService {
+    /// 查询外部账号绑定的用户, 未绑定时按策略绑定已有用户或创建用户,
+    /// 绑定的用户已停用时返回 `AppError::AccountDisabled`
+    async fn resolve_user(profile: &ExternalProfile, policy: ProvisionPolicy) -> Result<Users>;
+}
+
+impl ExternalIdentitiesService {
+    /// 按身份提供方已验证的邮箱查找已有用户
+    async fn find_by_verified_email(profile: &ExternalProfile) -> Result<Option<Users>> {
+        match &profile.email {
+            Some(email) if profile.email_verified => {
+                UsersService::find_by_email(&email.to_lowercase()).await
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// 生成未被占用的用户名, 冲突时追加随机后缀
+    async fn available_username(profile: &ExternalProfile) -> Result<String> {
+        let hint = username_hint(profile.username.as_deref(), profile.email.as_deref());
+        let mut username = hint.clone();
+        for _ in 0..USERNAME_ATTEMPTS {
+            if !UsersService::exists_by_username(&username).await? {
+                return Ok(username);
+            }
+            let suffix = rand::thread_rng().gen_range(1000..10000);
+            username = format!("{}-{}", hint, suffix);
+        }
+        Err(AppError::UsernameAlreadyExists.into())
+    }
+
+    /// 根据外部账号信息创建用户, 用户没有可用的本地密码
+    async fn provision(profile: &ExternalProfile) -> Result<Users> {
+        let email = match &profile.email {
+            Some(email) => email.to_lowercase(),
+            None => return Err(AppError::ExternalLoginFailed.into()),
+        };
+        if UsersService::exists_by_email(&email).await? {
+            return Err(AppError::EmailAlreadyExists.into());
+        }
+        let username = Self::available_username(profile).await?;
+        let new_user = NewUser {
+            nickname: profile.name.clone().unwrap_or_else(|| username.clone()),
+            username,
+            email,
+            password: String::new(),
+        };
+        let password_hash = CRYPTO
+            .generate_password_hash(&CRYPTO.generate_token())
+            .await?;
+        let user = UsersService::user_register(&new_user, &password_hash).await?;
+        if profile.email_verified {
+            UsersService::set_email_verified(&user.id).await?;
+        }
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl ExtExternalIdentitiesService for ExternalIdentitiesService {
+    async fn resolve_user(profile: &ExternalProfile, policy: ProvisionPolicy) -> Result<Users> {
+        // 已绑定的外部账号
+        if let Some(identity) =
+            ExternalIdentitiesRepository::find_for_login(&profile.provider, &profile.subject)
+                .await?
+        {
+            return match UsersService::find_by_id(&identity.user_id).await? {
+                Some(user) if user.active => Ok(user),
+                Some(_) => Err(AppError::AccountDisabled.into()),
+                None => Err(AppError::ExternalAccountNotLinked.into()),
+            };
+        }
+
+        let user = match Self::find_by_verified_email(profile).await? {
+            Some(user) if policy.link_by_email => user,
+            _ if policy.auto_provision => Self::provision(profile).await?,
+            _ => return Err(AppError::ExternalAccountNotLinked.into()),
+        };
+        ExternalIdentitiesRepository::create(
+            &user.id,
+            &profile.provider,
+            &profile.subject,
+            profile.email.as_deref(),
+        )
+        .await?;
+        log::info!(
+            "用户: [{}] 绑定了外部账号: [{}] [{}]",
+            user.username,
+            profile.provider,
+            profile.subject
+        );
+        Ok(user)
+    }
+}
+
+#[test]
+fn test_resolve_inactive_user() {
+    crate::block_on_db(async {
+        use crate::common::error::errors::is_app_error;
+        use crate::service::users::{create_test_user, deactivate_test_user};
+
+        let user = create_test_user("external-9Kx2").await;
+        let profile = ExternalProfile {
+            provider: "test".to_string(),
+            subject: user.id.to_string(),
+            username: None,
+            email: None,
+            email_verified: false,
+            name: None,
+        };
+        let policy = ProvisionPolicy {
+            auto_provision: false,
+            link_by_email: false,
+        };
+        ExternalIdentitiesRepository::create(&user.id, &profile.provider, &profile.subject, None)
+            .await
+            .unwrap();
+        let resolved = ExternalIdentitiesService::resolve_user(&profile, policy)
+            .await
+            .unwrap();
+        assert_eq!(resolved.id, user.id);
+
+        // 已停用的用户不能通过已绑定的外部账号登录
+        deactivate_test_user(&user.id).await;
+        let disabled = ExternalIdentitiesService::resolve_user(&profile, policy).await;
+        assert!(is_app_error(&disabled, AppError::AccountDisabled));
+    })
+}
@@ -0,0 +1,240 @@
+use std::future::Future;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::domain::audit::{AuditAction, NewAuditEvent};
+use crate::domain::mfa::{TotpEnrollment, UserTotp};
+use crate::domain::users::{Users, UsersToken};
+use crate::repository::mfa::{ExtMfaRepository, MfaRepository};
+use crate::security::auth::ClientInfo;
+use crate::security::throttle::LoginThrottle;
+use crate::security::totp;
+use crate::service::audit::{AuditService, ExtAuditService};
+use crate::service::auth::{AuthService, ExtAuthService};
+use crate::{CONFIGS, CRYPTO, REVOCATIONS};
+
+pub struct MfaService;
+
+#[async_trait]
+pub trait ExtMfaService {
+    /// 查询用户是否已启用两步验证
+    async fn is_enabled(user_id: &Uuid) -> Result<bool>;
+
+    /// 生成新的两步验证秘钥, 确认之前不会生效
+    async fn enroll_totp(user: &Users) -> Result<TotpEnrollment>;
+
+    /// 使用验证码确认绑定并启用两步验证, 返回恢复码
+    async fn confirm_totp(user_id: &Uuid, code: &str) -> Result<Vec<String>>;
+
+    /// 关闭两步验证, 需要提交验证码或恢复码
+    async fn disable_totp(user_id: &Uuid, code: &str) -> Result<()>;
+
+    /// 重新生成恢复码, 之前的恢复码全部失效
+    async fn regenerate_recovery_codes(user_id: &Uuid, code: &str) -> Result<Vec<String>>;
+
+    /// 使用两步验证令牌和验证码 (或恢复码) 完成登录, 两步验证令牌只能使用一次
+    async fn verify_mfa(mfa_token: &str, code: &str, client: &ClientInfo) -> Result<UsersToken>;
+}
+
+impl MfaService {
+    /// 验证 TOTP 验证码, 同一个验证码只能使用一次
+    async fn verify_totp(totp: &UserTotp, code: &str) -> Result<bool> {
+        let secret = CRYPTO.decrypt(&totp.secret)?;
+        match totp::verify(&secret, code, Utc::now())? {
+            Some(step) => MfaRepository::use_totp_step(&totp.user_id, step).await,
+            None => Ok(false),
+        }
+    }
+
+    /// 验证码与密码共用账号的登录失败限制, 验证码错误时记录失败, 验证通过后清除
+    async fn throttled<F>(user_id: &Uuid, verify: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>> + Send,
+    {
+        let account_key = LoginThrottle::account_key(&user_id.to_string());
+        LoginThrottle::check(std::slice::from_ref(&account_key)).await?;
+        if let Err(error) = verify.await {
+            if let Some(AppError::InvalidMfaCode) = error.downcast_ref::<AppError>() {
+                let max_failures = CONFIGS.auth.throttle.max_account_failures;
+                LoginThrottle::record_failure(&account_key, max_failures).await?;
+            }
+            return Err(error);
+        }
+        LoginThrottle::clear(&account_key).await?;
+        Ok(())
+    }
+
+    /// 验证已启用的两步验证的验证码或恢复码, 受账号的登录失败限制
+    async fn verify_code(user_id: &Uuid, code: &str) -> Result<()> {
+        Self::throttled(user_id, Self::check_code(user_id, code)).await
+    }
+
+    /// 不计失败次数地验证验证码或恢复码
+    async fn check_code(user_id: &Uuid, code: &str) -> Result<()> {
+        let totp = match MfaRepository::find_totp(user_id).await? {
+            Some(totp) if totp.enabled_at.is_some() => totp,
+            _ => return Err(AppError::MfaNotEnabled.into()),
+        };
+        if Self::verify_totp(&totp, code).await? {
+            return Ok(());
+        }
+        let code_hash = CRYPTO.hash_token(&totp::normalize_recovery_code(code))?;
+        if MfaRepository::use_recovery_code(user_id, &code_hash).await? {
+            log::info!("用户: [{}] 使用了恢复码", user_id);
+            return Ok(());
+        }
+        Err(AppError::InvalidMfaCode.into())
+    }
+
+    /// 生成并保存新的恢复码
+    async fn new_recovery_codes(user_id: &Uuid) -> Result<Vec<String>> {
+        let codes = totp::generate_recovery_codes();
+        let code_hashes = codes
+            .iter()
+            .map(|code| CRYPTO.hash_token(&totp::normalize_recovery_code(code)))
+            .collect::<Result<Vec<_>>>()?;
+        MfaRepository::replace_recovery_codes(user_id, &code_hashes).await?;
+        Ok(codes)
+    }
+}
+
+#[async_trait]
+impl ExtMfaService for MfaService {
+    async fn is_enabled(user_id: &Uuid) -> Result<bool> {
+        Ok(MfaRepository::find_totp(user_id)
+            .await?
+            .is_some_and(|totp| totp.enabled_at.is_some()))
+    }
+
+    async fn enroll_totp(user: &Users) -> Result<TotpEnrollment> {
+        let secret = totp::generate_secret();
+        let encrypted = CRYPTO.encrypt(&secret)?;
+        if MfaRepository::save_pending_totp(&user.id, &encrypted)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::MfaAlreadyEnabled.into());
+        }
+
+        Ok(TotpEnrollment {
+            secret: totp::base32(&secret),
+            otpauth_uri: totp::otpauth_uri(&CONFIGS.crypto.mfa.issuer, &user.username, &secret),
+        })
+    }
+
+    async fn confirm_totp(user_id: &Uuid, code: &str) -> Result<Vec<String>> {
+        let totp = match MfaRepository::find_totp(user_id).await? {
+            Some(totp) if totp.enabled_at.is_some() => {
+                return Err(AppError::MfaAlreadyEnabled.into())
+            }
+            Some(totp) => totp,
+            None => return Err(AppError::MfaNotEnabled.into()),
+        };
+        Self::throttled(user_id, async {
+            match Self::verify_totp(&totp, code).await? {
+                true => Ok(()),
+                false => Err(AppError::InvalidMfaCode.into()),
+            }
+        })
+        .await?;
+
+        let codes = Self::new_recovery_codes(user_id).await?;
+        if !MfaRepository::enable_totp(user_id).await? {
+            return Err(AppError::MfaAlreadyEnabled.into());
+        }
+        log::info!("用户: [{}] 启用了两步验证", user_id);
+        Ok(codes)
+    }
+
+    async fn disable_totp(user_id: &Uuid, code: &str) -> Result<()> {
+        Self::verify_code(user_id, code).await?;
+        MfaRepository::delete_totp(user_id).await?;
+        log::info!("用户: [{}] 关闭了两步验证", user_id);
+        Ok(())
+    }
+
+    async fn regenerate_recovery_codes(user_id: &Uuid, code: &str) -> Result<Vec<String>> {
+        Self::verify_code(user_id, code).await?;
+        Self::new_recovery_codes(user_id).await
+    }
+
+    async fn verify_mfa(mfa_token: &str, code: &str, client: &ClientInfo) -> Result<UsersToken> {
+        let claims = CRYPTO
+            .verify_mfa_token(mfa_token)
+            .await
+            .map_err(|_| AppError::InvalidMfaToken)?
+            .claims;
+        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AppError::InvalidMfaToken)?;
+
+        let result: Result<UsersToken> = async {
+            if REVOCATIONS.is_revoked(&claims.jti) {
+                return Err(AppError::InvalidMfaToken.into());
+            }
+            Self::verify_code(&user_id, code).await?;
+            // 两步验证令牌只能使用一次
+            let expires_at = Utc.timestamp(claims.exp, 0);
+            if !REVOCATIONS
+                .consume(&claims.jti, &user_id, &expires_at)
+                .await?
+            {
+                return Err(AppError::InvalidMfaToken.into());
+            }
+            AuthService::issue_tokens(&user_id, client).await
+        }
+        .await;
+
+        AuditService::record(
+            NewAuditEvent::new(AuditAction::MfaVerify, client)
+                .actor(&user_id)
+                .subject(&user_id)
+                .result(&result),
+        )
+        .await;
+        result
+    }
+}
+
+#[test]
+fn test_mfa_token_single_use() {
+    crate::block_on_db(async {
+        use crate::common::error::errors::is_app_error;
+        use crate::domain::mfa::SignInResult;
+        use crate::service::users::create_test_user;
+
+        let user = create_test_user("mfa-9Kx2").await;
+        let client = ClientInfo::default();
+        MfaService::enroll_totp(&user).await.unwrap();
+        let totp = MfaRepository::find_totp(&user.id).await.unwrap().unwrap();
+        let secret = CRYPTO.decrypt(&totp.secret).unwrap();
+        let code = totp::code_at(&secret, Utc::now().timestamp() / 30).unwrap();
+        let recovery_codes = MfaService::confirm_totp(&user.id, &format!("{:06}", code))
+            .await
+            .unwrap();
+
+        let mfa_token = match AuthService::sign_in(&user.id, &client).await.unwrap() {
+            SignInResult::MfaChallenge(challenge) => challenge.mfa_token,
+            SignInResult::Token(_) => panic!("启用两步验证后应返回两步验证令牌"),
+        };
+        assert!(
+            MfaService::verify_mfa(&mfa_token, &recovery_codes[0], &client)
+                .await
+                .is_ok()
+        );
+        // 同一个两步验证令牌即使提交新的恢复码也不能再次使用
+        let replayed = MfaService::verify_mfa(&mfa_token, &recovery_codes[1], &client).await;
+        assert!(is_app_error(&replayed, AppError::InvalidMfaToken));
+
+        // 关闭两步验证时连续提交错误的验证码会被锁定
+        let max_failures = CONFIGS.auth.throttle.max_account_failures;
+        for _ in 0..=max_failures {
+            let disabled = MfaService::disable_totp(&user.id, "000000").await;
+            assert!(is_app_error(&disabled, AppError::InvalidMfaCode));
+        }
+        let locked = MfaService::disable_totp(&user.id, &recovery_codes[1]).await;
+        assert!(is_app_error(&locked, AppError::TooManyAttempts(0)));
+    })
+}
@@ -1 +1,10 @@
+pub mod access_tokens;
+pub mod audit;
+pub mod auth;
+pub mod email_verification;
+pub mod external_identities;
+pub mod mfa;
+pub mod oidc;
+pub mod password;
+pub mod roles;
 pub mod users;
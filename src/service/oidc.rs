@@ -0,0 +1,101 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::common::error::errors::AppError;
+use crate::domain::oidc::OidcAuthorization;
+use crate::domain::users::Users;
+use crate::security::oidc::{self, OidcClient};
+use crate::service::external_identities::{
+    ExtExternalIdentitiesService, ExternalIdentitiesService, ExternalProfile, ProvisionPolicy,
+};
+use crate::{CONFIGS, CRYPTO, OIDC};
+
+pub struct OidcService;
+
+/// 加密登录状态的用途, 与两步验证秘钥等共用加密秘钥, 按用途区分密文
+const STATE_PURPOSE: &[u8] = b"oidc-state";
+
+/// 登录状态, 加密后作为 state 参数, 回调时解密取回 code_verifier 和 nonce
+#[derive(Serialize, Deserialize)]
+struct OidcState {
+    provider: String,
+    code_verifier: String,
+    nonce: String,
+    exp: i64,
+}
+
+#[async_trait]
+pub trait ExtOidcService {
+    /// 发起第三方登录, 返回身份提供方的授权地址
+    async fn authorize(provider: &str) -> Result<OidcAuthorization>;
+
+    /// 使用回调中的授权码完成第三方登录, 返回绑定的用户, 允许时自动创建用户
+    async fn sign_in(provider: &str, code: &str, state: &str) -> Result<Users>;
+}
+
+impl OidcService {
+    fn client(provider: &str) -> Result<&'static OidcClient> {
+        OIDC.get(provider)
+            .ok_or_else(|| AppError::RequestParameterError.into())
+    }
+
+    /// 解密并校验登录状态
+    fn decode_state(provider: &str, state: &str) -> Option<OidcState> {
+        let plaintext = CRYPTO.decrypt_for(STATE_PURPOSE, state).ok()?;
+        let state = serde_json::from_slice::<OidcState>(&plaintext).ok()?;
+        if state.provider != provider || state.exp <= Utc::now().timestamp() {
+            return None;
+        }
+        Some(state)
+    }
+}
+
+#[async_trait]
+impl ExtOidcService for OidcService {
+    async fn authorize(provider: &str) -> Result<OidcAuthorization> {
+        let client = Self::client(provider)?;
+        let oidc_state = OidcState {
+            provider: provider.to_string(),
+            code_verifier: oidc::generate_random(),
+            nonce: oidc::generate_random(),
+            exp: (Utc::now() + CONFIGS.auth.oidc.get_state_expires()).timestamp(),
+        };
+        let state = CRYPTO.encrypt_for(STATE_PURPOSE, &serde_json::to_vec(&oidc_state)?)?;
+        let url = client
+            .authorization_url(
+                &state,
+                &oidc_state.nonce,
+                &oidc::code_challenge(&oidc_state.code_verifier),
+            )
+            .await?;
+        Ok(OidcAuthorization { url, state })
+    }
+
+    async fn sign_in(provider: &str, code: &str, state: &str) -> Result<Users> {
+        let client = Self::client(provider)?;
+        let state = Self::decode_state(provider, state).ok_or(AppError::ExternalLoginFailed)?;
+        let claims = client
+            .exchange_code(code, &state.code_verifier, &state.nonce)
+            .await
+            .map_err(|error| {
+                log::warn!("第三方登录: [{}] 失败: {:#}", provider, error);
+                AppError::ExternalLoginFailed
+            })?;
+
+        let profile = ExternalProfile {
+            provider: provider.to_string(),
+            subject: claims.sub,
+            username: claims.preferred_username,
+            email: claims.email,
+            email_verified: claims.email_verified,
+            name: claims.name,
+        };
+        let policy = ProvisionPolicy {
+            auto_provision: client.config.auto_provision,
+            link_by_email: client.config.link_by_email,
+        };
+        ExternalIdentitiesService::resolve_user(&profile, policy).await
+    }
+}
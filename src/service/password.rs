@@ -0,0 +1,214 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::common::mail::mailer::Mail;
+use crate::domain::users::Users;
+use crate::repository::password_resets::{ExtPasswordResetsRepository, PasswordResetsRepository};
+use crate::repository::users::{ExtUsersRepository, UsersRepository};
+use crate::security::password_policy::PasswordPolicy;
+use crate::service::auth::{AuthService, ExtAuthService};
+use crate::{AUTH_PROVIDERS, CONFIGS, CRYPTO, MAILER};
+
+pub struct PasswordService;
+
+#[async_trait]
+pub trait ExtPasswordService {
+    /// 发送重置密码邮件, 邮箱不存在时什么也不做, 避免泄露邮箱是否注册
+    async fn request_reset(email: &str) -> Result<()>;
+
+    /// 使用重置密码令牌设置新密码, 成功后退出所有设备, 返回用户id, 密码由外部目录管理的用户不能重置
+    async fn reset_password(token: &str, new_password: &str) -> Result<Uuid>;
+
+    /// 验证当前密码后修改密码 (与登录共用登录失败限制), 成功后注销当前会话以外的所有会话,
+    /// 密码由外部目录管理的用户不能修改
+    async fn change_password(
+        user: &Users,
+        current_sid: &Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<()>;
+}
+
+impl PasswordService {
+    /// 检查新密码是否符合密码策略
+    async fn check_policy(user: &Users, new_password: &str) -> Result<()> {
+        PasswordPolicy::check(new_password, &[&user.username, &user.email, &user.nickname]).await
+    }
+
+    /// 密码由外部目录管理的用户只能在目录中修改密码
+    async fn check_local(user: &Users) -> Result<()> {
+        if AUTH_PROVIDERS.is_external(user).await? {
+            return Err(AppError::ExternalPassword.into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExtPasswordService for PasswordService {
+    async fn request_reset(email: &str) -> Result<()> {
+        let user = match UsersRepository::find_by_email(email).await? {
+            Some(user) if user.active => user,
+            _ => {
+                log::info!("重置密码: 邮箱 [{}] 不存在或用户已禁用", email);
+                return Ok(());
+            }
+        };
+        // 不返回错误, 避免通过重置密码判断用户来源
+        if AUTH_PROVIDERS.is_external(&user).await? {
+            log::info!("重置密码: 用户 [{}] 的密码由外部目录管理", &user.username);
+            return Ok(());
+        }
+
+        // 只保留最新的重置密码令牌
+        PasswordResetsRepository::delete_unused_by_user(&user.id).await?;
+        let token = CRYPTO.generate_token();
+        let expires = CONFIGS.auth.get_password_reset_expires();
+        PasswordResetsRepository::create(
+            &user.id,
+            &CRYPTO.hash_token(&token)?,
+            &(Utc::now() + expires),
+        )
+        .await?;
+
+        let url = CONFIGS.auth.password_reset_url.replace("{token}", &token);
+        MAILER
+            .send(Mail {
+                to: user.email,
+                subject: "重置密码".to_string(),
+                body: format!(
+                    "{} 您好:\n\n请在 {} 分钟内打开以下链接重置密码:\n{}\n\n如果不是您本人操作, 请忽略此邮件.",
+                    user.nickname,
+                    expires.num_minutes(),
+                    url
+                ),
+            })
+            .await
+    }
+
+    async fn reset_password(token: &str, new_password: &str) -> Result<Uuid> {
+        let token_hash = CRYPTO.hash_token(token)?;
+        // 先检查密码策略再使用令牌, 密码不符合要求时令牌仍然可以使用
+        let user = match PasswordResetsRepository::find_user_id(&token_hash).await? {
+            Some(user_id) => UsersRepository::find_by_id(&user_id).await?,
+            None => None,
+        }
+        .ok_or(AppError::InvalidPasswordResetToken)?;
+        Self::check_local(&user).await?;
+        Self::check_policy(&user, new_password).await?;
+
+        let user_id = PasswordResetsRepository::consume(&token_hash)
+            .await?
+            .ok_or(AppError::InvalidPasswordResetToken)?;
+
+        let password_hash = CRYPTO.generate_password_hash(new_password).await?;
+        UsersRepository::update_password_hash(&user_id, &password_hash).await?;
+        PasswordResetsRepository::delete_unused_by_user(&user_id).await?;
+        AuthService::logout_everywhere(&user_id).await?;
+        log::info!("用户: [{}] 重置了密码", user_id);
+        Ok(user_id)
+    }
+
+    async fn change_password(
+        user: &Users,
+        current_sid: &Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<()> {
+        AuthService::verify_password(user, current_password).await?;
+        Self::check_local(user).await?;
+        Self::check_policy(user, new_password).await?;
+
+        let password_hash = CRYPTO.generate_password_hash(new_password).await?;
+        UsersRepository::update_password_hash(&user.id, &password_hash).await?;
+        PasswordResetsRepository::delete_unused_by_user(&user.id).await?;
+        for session in AuthService::find_sessions(&user.id, current_sid).await? {
+            if !session.current {
+                AuthService::revoke_session(&user.id, &session.id).await?;
+            }
+        }
+        log::info!("用户: [{}] 修改了密码", user.username);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_reset_token_single_use() {
+    crate::block_on_db(async {
+        use crate::common::error::errors::is_app_error;
+        use crate::service::users::create_test_user;
+
+        let user = create_test_user("reset-9Kx2-Vq7!").await;
+        let token = CRYPTO.generate_token();
+        PasswordResetsRepository::create(
+            &user.id,
+            &CRYPTO.hash_token(&token).unwrap(),
+            &(Utc::now() + chrono::Duration::minutes(5)),
+        )
+        .await
+        .unwrap();
+
+        // 新密码不符合密码策略时令牌不会被使用
+        let weak = PasswordService::reset_password(&token, "123").await;
+        assert!(is_app_error(&weak, AppError::WeakPassword(vec![])));
+
+        let user_id = PasswordService::reset_password(&token, "Reset-Mz4p-Wq8#")
+            .await
+            .unwrap();
+        assert_eq!(user_id, user.id);
+        let reused = PasswordService::reset_password(&token, "Reset-Lh3t-Xe5$").await;
+        assert!(is_app_error(&reused, AppError::InvalidPasswordResetToken));
+    })
+}
+
+#[test]
+fn test_change_password() {
+    crate::block_on_db(async {
+        use crate::common::error::errors::is_app_error;
+        use crate::security::auth::{authenticate, ClientInfo};
+        use crate::service::users::create_test_user;
+
+        let user = create_test_user("change-9Kx2-Vq7!").await;
+        let client = ClientInfo::default();
+        let current = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let other = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let current_sid = authenticate(&current.access_token)
+            .await
+            .unwrap()
+            .claims()
+            .unwrap()
+            .sid;
+
+        // 修改密码后只保留当前会话
+        PasswordService::change_password(
+            &user,
+            &current_sid,
+            "change-9Kx2-Vq7!",
+            "Change-Mz4p-Wq8#",
+        )
+        .await
+        .unwrap();
+        assert!(authenticate(&current.access_token).await.is_ok());
+        assert!(authenticate(&other.access_token).await.is_err());
+
+        // 当前密码错误计入登录失败次数, 超过次数后即使密码正确也被锁定
+        let max_failures = CONFIGS.auth.throttle.max_account_failures;
+        for _ in 0..=max_failures {
+            let changed =
+                PasswordService::change_password(&user, &current_sid, "wrong", "Change-Lh3t-Xe5$")
+                    .await;
+            assert!(is_app_error(&changed, AppError::UsernameOrPasswordError));
+        }
+        let locked = PasswordService::change_password(
+            &user,
+            &current_sid,
+            "Change-Mz4p-Wq8#",
+            "Change-Lh3t-Xe5$",
+        )
+        .await;
+        assert!(is_app_error(&locked, AppError::TooManyAttempts(0)));
+    })
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::common::error::errors::AppError;
+use crate::repository::roles::{ExtRolesRepository, RolesRepository};
+use crate::repository::users::{ExtUsersRepository, UsersRepository};
+
+pub struct RolesService;
+
+#[async_trait]
+pub trait ExtRolesService {
+    /// 查询用户的角色名称
+    async fn find_names_by_user(user_id: &Uuid) -> Result<Vec<String>>;
+
+    /// 查询角色拥有的权限名称
+    async fn find_permissions_by_roles(roles: &[String]) -> Result<Vec<String>>;
+
+    /// 为用户分配角色, 已分配时返回 false, 用户或角色不存在时返回 `AppError::RequestParameterError`
+    async fn assign(user_id: &Uuid, role: &str) -> Result<bool>;
+
+    /// 移除用户的角色, 未分配时返回 false, 用户或角色不存在时返回 `AppError::RequestParameterError`
+    async fn remove(user_id: &Uuid, role: &str) -> Result<bool>;
+}
+
+impl RolesService {
+    /// 检查用户和角色是否存在
+    async fn check_user_role(user_id: &Uuid, role: &str) -> Result<()> {
+        if UsersRepository::find_by_id(user_id).await?.is_none()
+            || !RolesRepository::exists_by_name(role).await?
+        {
+            return Err(AppError::RequestParameterError.into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExtRolesService for RolesService {
+    async fn find_names_by_user(user_id: &Uuid) -> Result<Vec<String>> {
+        RolesRepository::find_names_by_user(user_id).await
+    }
+
+    async fn find_permissions_by_roles(roles: &[String]) -> Result<Vec<String>> {
+        RolesRepository::find_permissions_by_roles(roles).await
+    }
+
+    async fn assign(user_id: &Uuid, role: &str) -> Result<bool> {
+        Self::check_user_role(user_id, role).await?;
+        RolesRepository::assign(user_id, role).await
+    }
+
+    async fn remove(user_id: &Uuid, role: &str) -> Result<bool> {
+        Self::check_user_role(user_id, role).await?;
+        RolesRepository::remove(user_id, role).await
+    }
+}
+
+#[test]
+fn test_assign_role() {
+    crate::block_on_db(async {
+        use crate::common::error::errors::is_app_error;
+        use crate::security::guards::{ROLE_ADMIN, ROLE_USER};
+        use crate::service::users::create_test_user;
+
+        // 注册时分配默认角色
+        let user = create_test_user("roles-9Kx2q").await;
+        assert_eq!(
+            RolesService::find_names_by_user(&user.id).await.unwrap(),
+            vec![ROLE_USER]
+        );
+
+        assert!(!RolesService::assign(&user.id, ROLE_USER).await.unwrap());
+        assert!(RolesService::assign(&user.id, ROLE_ADMIN).await.unwrap());
+        assert!(RolesService::remove(&user.id, ROLE_ADMIN).await.unwrap());
+        assert!(!RolesService::remove(&user.id, ROLE_ADMIN).await.unwrap());
+
+        // 角色或用户不存在
+        let result = RolesService::assign(&user.id, "no-such-role").await;
+        assert!(is_app_error(&result, AppError::RequestParameterError));
+        let result = RolesService::remove(&user.id, "no-such-role").await;
+        assert!(is_app_error(&result, AppError::RequestParameterError));
+        let result = RolesService::assign(&Uuid::new_v4(), ROLE_USER).await;
+        assert!(is_app_error(&result, AppError::RequestParameterError));
+    })
+}
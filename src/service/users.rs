@@ -1,16 +1,21 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use uuid::Uuid;
 
 use crate::domain::users::{NewUser, Users};
 use crate::repository::users::{ExtUsersRepository, UsersRepository};
+use crate::security::guards::ROLE_USER;
 
 pub struct UsersService;
 
 #[async_trait]
 pub trait ExtUsersService {
-    /// 注册用户
+    /// 注册用户, 同时分配默认角色
     async fn user_register(new_user: &NewUser, password_hash: &str) -> Result<Users>;
 
+    /// 根据id查询用户
+    async fn find_by_id(id: &Uuid) -> Result<Option<Users>>;
+
     /// 根据用户名查询用户
     async fn find_by_username(username: &str) -> Result<Option<Users>>;
 
@@ -25,12 +30,23 @@ pub trait ExtUsersService {
 
     /// 检查邮箱是否存在
     async fn exists_by_email(email: &str) -> Result<bool>;
+
+    /// 更新密码哈希
+    async fn update_password_hash(id: &Uuid, password_hash: &str) -> Result<()>;
+
+    /// 标记邮箱已验证
+    async fn set_email_verified(id: &Uuid) -> Result<()>;
 }
 
 #[async_trait]
 impl ExtUsersService for UsersService {
     async fn user_register(new_user: &NewUser, password_hash: &str) -> Result<Users> {
-        UsersRepository::create(new_user, password_hash).await
+        // 分配默认角色
+        UsersRepository::create(new_user, password_hash, ROLE_USER).await
+    }
+
+    async fn find_by_id(id: &Uuid) -> Result<Option<Users>> {
+        UsersRepository::find_by_id(id).await
     }
 
     async fn find_by_username(username: &str) -> Result<Option<Users>> {
@@ -52,4 +68,42 @@ impl ExtUsersService for UsersService {
     async fn exists_by_email(email: &str) -> Result<bool> {
         UsersRepository::exists_by_email(email).await
     }
+
+    async fn update_password_hash(id: &Uuid, password_hash: &str) -> Result<()> {
+        UsersRepository::update_password_hash(id, password_hash).await
+    }
+
+    async fn set_email_verified(id: &Uuid) -> Result<()> {
+        UsersRepository::set_email_verified(id).await
+    }
+}
+
+/// 创建测试用户, 用户名和邮箱随机生成,
+/// 需要本地数据库 (`docker/docker-compose.yaml` 中的 postgres, 并执行 `migrations` 下的迁移)
+#[cfg(test)]
+pub async fn create_test_user(password: &str) -> Users {
+    let username = format!("t{}", &Uuid::new_v4().to_simple().to_string()[..12]);
+    let new_user = NewUser {
+        email: format!("{}@example.com", username),
+        nickname: username.clone(),
+        username,
+        password: password.to_string(),
+    };
+    let password_hash = crate::CRYPTO
+        .generate_password_hash(password)
+        .await
+        .unwrap();
+    UsersService::user_register(&new_user, &password_hash)
+        .await
+        .unwrap()
+}
+
+/// 停用测试用户
+#[cfg(test)]
+pub async fn deactivate_test_user(id: &Uuid) {
+    sqlx::query("UPDATE users SET active = false WHERE id = $1")
+        .bind(id)
+        .execute(&*crate::POOL)
+        .await
+        .unwrap();
 }
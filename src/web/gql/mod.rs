@@ -1,18 +1,26 @@
 use async_graphql::extensions::{ApolloTracing, Logger};
+use async_graphql::parser::{self, types::Selection};
 use async_graphql::{
     http::{playground_source, GraphQLPlaygroundConfig},
     Request,
 };
-use async_graphql::{EmptySubscription, Schema};
+use async_graphql::{Context, EmptySubscription, ErrorExtensions, Schema};
 
 use mutations::MutationRoot;
 use queries::QueryRoot;
 use warp::{
-    http::{Error, Response},
+    http::{
+        header::{AUTHORIZATION, USER_AGENT},
+        Error, Response,
+    },
     Filter, Rejection,
 };
 
+use crate::common::error::errors::AppError;
 use crate::config::configs::Configs;
+use crate::domain::audit::{AuditAction, NewAuditEvent, OUTCOME_FAILURE};
+use crate::security::auth::{self, ClientInfo, CurrentUser};
+use crate::service::audit::{AuditService, ExtAuditService};
 use std::{convert::Infallible, sync::Arc};
 
 pub mod mutations;
@@ -24,6 +32,72 @@ pub type ServiceSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 /// 定义返回
 pub type GraphqlResult<T> = std::result::Result<T, async_graphql::Error>;
 
+/// 获取客户端信息
+pub fn client_info(ctx: &Context<'_>) -> ClientInfo {
+    ctx.data_opt::<ClientInfo>().cloned().unwrap_or_default()
+}
+
+/// 创建审计事件, 已登录时操作人为当前用户, 代登录时为实际操作的管理员
+pub fn audit_event(ctx: &Context<'_>, action: AuditAction) -> NewAuditEvent {
+    let event = NewAuditEvent::new(action, &client_info(ctx));
+    match ctx.data_opt::<CurrentUser>() {
+        Some(current_user) => match &current_user.impersonator {
+            Some(impersonator) => event
+                .actor(impersonator)
+                .metadata("impersonating", current_user.user.id.to_string()),
+            None => event.actor(&current_user.user.id),
+        },
+        None => event,
+    }
+}
+
+/// 代登录期间请求的审计事件, 只记录调用的操作和字段, 不记录参数
+fn impersonated_request_event(
+    current_user: &CurrentUser,
+    client: &ClientInfo,
+    request: &Request,
+) -> Option<NewAuditEvent> {
+    let impersonator = current_user.impersonator.as_ref()?;
+    let fields = match parser::parse_query(&request.query) {
+        Ok(document) => document
+            .operations
+            .iter()
+            .filter(|(name, _)| match &request.operation_name {
+                Some(operation_name) => name.map(|name| name.as_str()) == Some(operation_name),
+                None => true,
+            })
+            .flat_map(|(_, operation)| {
+                let ty = operation.node.ty;
+                operation
+                    .node
+                    .selection_set
+                    .node
+                    .items
+                    .iter()
+                    .filter_map(move |selection| match &selection.node {
+                        Selection::Field(field) => Some(format!("{} {}", ty, field.node.name.node)),
+                        _ => None,
+                    })
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+    let event = NewAuditEvent::new(AuditAction::ImpersonatedRequest, client)
+        .actor(impersonator)
+        .subject(&current_user.user.id)
+        .metadata("fields", fields);
+    Some(match &request.operation_name {
+        Some(operation_name) => event.metadata("operation", operation_name.as_str()),
+        None => event,
+    })
+}
+
+/// 获取当前登录用户, 未登录时返回 `AppError::Unauthenticated`
+pub fn current_user<'a>(ctx: &Context<'a>) -> GraphqlResult<&'a CurrentUser> {
+    ctx.data_opt::<CurrentUser>()
+        .ok_or_else(|| AppError::Unauthenticated.extend())
+}
+
 // graphql 入口
 pub fn graphql(
     config: Arc<Configs>,
@@ -40,13 +114,46 @@ pub fn graphql(
         schema = schema.extension(ApolloTracing);
     }
 
+    let trust_proxy = config.server.trust_proxy;
+    let client_info = warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and(warp::header::optional::<String>(USER_AGENT.as_str()))
+        .map(move |remote, forwarded_for: Option<String>, user_agent| {
+            ClientInfo::new(remote, forwarded_for.as_deref(), user_agent, trust_proxy)
+        });
+
     warp::path(config.graphql.path.clone())
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(client_info)
         .and(async_graphql_warp::graphql(schema.finish()))
-        .and_then(|(schema, request): (ServiceSchema, Request)| async move {
-            Ok::<_, Infallible>(async_graphql_warp::Response::from(
-                schema.execute(request).await,
-            ))
-        })
+        .and_then(
+            |authorization: Option<String>,
+             client_info: ClientInfo,
+             (schema, request): (ServiceSchema, Request)| async move {
+                let mut audit = None;
+                let mut request = request.data(client_info.clone());
+                // 认证通过的用户放入请求上下文
+                if let Some(current_user) = auth::authenticate_header(authorization).await {
+                    audit = impersonated_request_event(&current_user, &client_info, &request);
+                    request = request.data(current_user);
+                }
+                let response = schema.execute(request).await;
+                // 代登录期间的每个请求都记录到审计日志
+                if let Some(mut audit) = audit {
+                    if response.is_err() {
+                        audit.outcome = OUTCOME_FAILURE;
+                        let errors = response
+                            .errors
+                            .iter()
+                            .map(|error| error.message.clone())
+                            .collect::<Vec<_>>();
+                        audit = audit.metadata("errors", errors);
+                    }
+                    AuditService::record(audit).await;
+                }
+                Ok::<_, Infallible>(async_graphql_warp::Response::from(response))
+            },
+        )
 }
 
 // GraphQLPlayground 入口
@@ -1,52 +1,630 @@
+use async_graphql::guard::Guard;
 use async_graphql::*;
+use uuid::Uuid;
 use validator::*;
 
+use crate::domain::audit::AuditAction;
+use crate::domain::mfa::TotpEnrollment;
+use crate::domain::tokens::{CreatedAccessToken, NewAccessToken};
+use crate::security::guards::{
+    NotImpersonatingGuard, PermissionGuard, RecentAuthGuard, SessionGuard, PERMISSION_ROLES_MANAGE,
+    PERMISSION_USERS_IMPERSONATE, PERMISSION_USERS_MANAGE,
+};
+use crate::security::password_policy::PasswordPolicy;
+use crate::security::throttle::LoginThrottle;
+use crate::service::access_tokens::{AccessTokensService, ExtAccessTokensService};
+use crate::service::audit::{AuditService, ExtAuditService};
+use crate::service::auth::{AuthService, ExtAuthService};
+use crate::service::email_verification::{EmailVerificationService, ExtEmailVerificationService};
+use crate::service::mfa::{ExtMfaService, MfaService};
+use crate::service::password::{ExtPasswordService, PasswordService};
+use crate::service::roles::{ExtRolesService, RolesService};
 use crate::service::users::{ExtUsersService, UsersService};
-use crate::web::gql::GraphqlResult;
+use crate::web::gql::{audit_event, client_info, current_user, GraphqlResult};
 use crate::{common::error::errors::AppError, domain::users::NewUser};
-use crate::{domain::users::Users, CRYPTO};
+use crate::{
+    domain::users::{ImpersonationToken, Users, UsersToken},
+    CRYPTO,
+};
 
 /// 变更根节点
 #[derive(MergedObject, Default)]
-pub struct MutationRoot(UsersMutation);
+pub struct MutationRoot(
+    UsersMutation,
+    MfaMutation,
+    AccessTokensMutation,
+    AdminMutation,
+);
 
 /// 用户变更 Mutation
 #[derive(Default)]
 pub struct UsersMutation;
 
+/// 两步验证变更 Mutation
+#[derive(Default)]
+pub struct MfaMutation;
+
+/// 个人访问令牌变更 Mutation
+#[derive(Default)]
+pub struct AccessTokensMutation;
+
+/// 管理员变更 Mutation, 代登录期间都不能调用
+#[derive(Default)]
+pub struct AdminMutation;
+
+impl UsersMutation {
+    /// 注册用户, 返回新用户
+    async fn register(new_user: &NewUser) -> anyhow::Result<Users> {
+        // 检查密码策略
+        PasswordPolicy::check(
+            &new_user.password,
+            &[&new_user.username, &new_user.email, &new_user.nickname],
+        )
+        .await?;
+
+        // 检查用户名重复
+        if UsersService::exists_by_username(&new_user.username).await? {
+            return Err(AppError::UsernameAlreadyExists.into());
+        }
+
+        // 检查邮箱重复
+        if UsersService::exists_by_email(&new_user.email).await? {
+            return Err(AppError::EmailAlreadyExists.into());
+        }
+
+        // 密码哈希
+        let password_hash = CRYPTO.generate_password_hash(&new_user.password).await?;
+
+        let user = UsersService::user_register(new_user, &password_hash).await?;
+
+        // 发送验证邮件, 失败不影响注册, 用户可以重新发送
+        if let Err(error) = EmailVerificationService::send_verification(&user).await {
+            log::error!("用户: [{}] 发送验证邮件失败: {:#}", &user.username, error);
+        }
+        Ok(user)
+    }
+}
+
 #[Object]
 impl UsersMutation {
     /// 注册用户
     async fn user_register(
         &self,
-        _ctx: &Context<'_>,
+        ctx: &Context<'_>,
         mut new_user: NewUser,
     ) -> GraphqlResult<Users> {
         // 参数校验
-        new_user
-            .validate()?;
-            // .map_err(AppError::RequestParameterError.validation_extend())?;
+        new_user.validate()?;
+        // .map_err(AppError::RequestParameterError.validation_extend())?;
 
         // 处理为 小写
         new_user.username.make_ascii_lowercase();
         new_user.email.make_ascii_lowercase();
 
-        // 检查用户名重复
-        let exists = UsersService::exists_by_username(&new_user.username).await?;
-        if exists {
-            return Err(AppError::UsernameAlreadyExists.extend());
+        let result = Self::register(&new_user).await;
+        let audit = audit_event(ctx, AuditAction::Register)
+            .login(&new_user.username)
+            .metadata("email", new_user.email.as_str());
+        let audit = match &result {
+            Ok(user) => audit.subject(&user.id),
+            Err(_) => audit,
+        };
+        AuditService::record(audit.result(&result)).await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 使用刷新令牌换取新的令牌
+    async fn refresh_token(
+        &self,
+        ctx: &Context<'_>,
+        refresh_token: String,
+    ) -> GraphqlResult<UsersToken> {
+        Ok(
+            AuthService::refresh_tokens(&refresh_token, &client_info(ctx))
+                .await
+                .map_err(AppError::service_extend)?,
+        )
+    }
+
+    /// 验证邮箱
+    async fn verify_email(&self, ctx: &Context<'_>, token: String) -> GraphqlResult<bool> {
+        let result = EmailVerificationService::verify_email(&token).await;
+        let audit = audit_event(ctx, AuditAction::EmailVerify);
+        let audit = match &result {
+            Ok(user_id) => audit.subject(user_id),
+            Err(_) => audit,
+        };
+        AuditService::record(audit.result(&result)).await;
+
+        result.map_err(AppError::service_extend)?;
+        Ok(true)
+    }
+
+    /// 重新发送验证邮件, 无论邮箱是否注册都立即返回成功
+    async fn resend_verification_email(
+        &self,
+        ctx: &Context<'_>,
+        email: String,
+    ) -> GraphqlResult<bool> {
+        let email = email.to_lowercase();
+        let audit = audit_event(ctx, AuditAction::EmailVerificationResend).login(&email);
+        // 在后台查询邮箱并发送邮件, 响应时间与邮箱是否注册无关
+        tokio::spawn(async move {
+            let result = EmailVerificationService::resend(&email).await;
+            if let Err(error) = &result {
+                log::error!("重新发送验证邮件: [{}] 失败: {:#}", email, error);
+            }
+            AuditService::record(audit.result(&result)).await;
+        });
+        Ok(true)
+    }
+
+    /// 发送重置密码邮件, 无论邮箱是否注册都立即返回成功
+    async fn request_password_reset(
+        &self,
+        ctx: &Context<'_>,
+        email: String,
+    ) -> GraphqlResult<bool> {
+        let email = email.to_lowercase();
+        let audit = audit_event(ctx, AuditAction::PasswordResetRequest).login(&email);
+        // 在后台查询邮箱并发送邮件, 响应时间与邮箱是否注册无关
+        tokio::spawn(async move {
+            let result = PasswordService::request_reset(&email).await;
+            if let Err(error) = &result {
+                log::error!("发送重置密码邮件: [{}] 失败: {:#}", email, error);
+            }
+            AuditService::record(audit.result(&result)).await;
+        });
+        Ok(true)
+    }
+
+    /// 使用邮件中的令牌重置密码, 密码由外部目录管理的用户需要在目录中修改
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+        new_password: String,
+    ) -> GraphqlResult<bool> {
+        let result = PasswordService::reset_password(&token, &new_password).await;
+        let audit = audit_event(ctx, AuditAction::PasswordReset);
+        let audit = match &result {
+            Ok(user_id) => audit.subject(user_id),
+            Err(_) => audit,
+        };
+        AuditService::record(audit.result(&result)).await;
+
+        result.map_err(AppError::service_extend)?;
+        Ok(true)
+    }
+
+    /// 修改密码, 需要提交当前密码, 成功后注销当前会话以外的所有会话,
+    /// 密码由外部目录管理的用户需要在目录中修改
+    #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+    async fn change_password(
+        &self,
+        ctx: &Context<'_>,
+        current_password: String,
+        new_password: String,
+    ) -> GraphqlResult<bool> {
+        let current_user = current_user(ctx)?;
+        let claims = current_user
+            .claims()
+            .ok_or_else(|| AppError::Forbidden.extend())?;
+        let result = PasswordService::change_password(
+            &current_user.user,
+            &claims.sid,
+            &current_password,
+            &new_password,
+        )
+        .await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::PasswordChange)
+                .subject(&current_user.user.id)
+                .result(&result),
+        )
+        .await;
+
+        result.map_err(AppError::service_extend)?;
+        Ok(true)
+    }
+
+    /// 重新验证密码, 返回新的令牌, 新令牌可以在一段时间内执行修改两步验证等敏感操作
+    #[graphql(guard(SessionGuard()))]
+    async fn reauthenticate(
+        &self,
+        ctx: &Context<'_>,
+        password: String,
+    ) -> GraphqlResult<UsersToken> {
+        let current_user = current_user(ctx)?;
+        let claims = current_user
+            .claims()
+            .ok_or_else(|| AppError::Forbidden.extend())?;
+        let result = AuthService::reauthenticate(&current_user.user, claims, &password).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::Reauthenticate)
+                .subject(&current_user.user.id)
+                .metadata("session", claims.sid.to_string())
+                .result(&result),
+        )
+        .await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 退出登录, 代登录时用于结束代登录
+    async fn logout(&self, ctx: &Context<'_>) -> GraphqlResult<bool> {
+        let current_user = current_user(ctx)?;
+        let claims = current_user
+            .claims()
+            .ok_or_else(|| AppError::Forbidden.extend())?;
+        let result = AuthService::logout(claims).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::SignOut)
+                .subject(&current_user.user.id)
+                .metadata("session", claims.sid.to_string())
+                .result(&result),
+        )
+        .await;
+
+        result.map_err(AppError::service_extend)?;
+        Ok(true)
+    }
+
+    /// 注销指定的会话 (例如丢失的设备), 会话不存在时返回 false
+    ///
+    /// 注销只会收回访问权限, 不要求重新验证密码, 以便发现异常时尽快处理
+    #[graphql(guard(SessionGuard()))]
+    async fn revoke_session(&self, ctx: &Context<'_>, id: Uuid) -> GraphqlResult<bool> {
+        let current_user = current_user(ctx)?;
+        let result = AuthService::revoke_session(&current_user.user.id, &id).await;
+        // 会话不存在时不记录
+        if !matches!(result, Ok(false)) {
+            AuditService::record(
+                audit_event(ctx, AuditAction::SessionRevoke)
+                    .subject(&current_user.user.id)
+                    .metadata("session", id.to_string())
+                    .result(&result),
+            )
+            .await;
         }
 
-        // 检查邮箱重复
-        let exists = UsersService::exists_by_email(&new_user.email).await?;
-        if exists {
-            return Err(AppError::EmailAlreadyExists.extend());
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 退出所有设备, 与注销会话相同, 不要求重新验证密码
+    #[graphql(guard(SessionGuard()))]
+    async fn logout_everywhere(&self, ctx: &Context<'_>) -> GraphqlResult<bool> {
+        let current_user = current_user(ctx)?;
+        let result = AuthService::logout_everywhere(&current_user.user.id).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::SignOutEverywhere)
+                .subject(&current_user.user.id)
+                .result(&result),
+        )
+        .await;
+
+        result.map_err(AppError::service_extend)?;
+        Ok(true)
+    }
+}
+
+#[Object]
+impl MfaMutation {
+    /// 绑定两步验证, 返回秘钥和 otpauth URI, 需要调用 confirmTotp 确认后才会启用
+    #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+    async fn enroll_totp(&self, ctx: &Context<'_>) -> GraphqlResult<TotpEnrollment> {
+        let current_user = current_user(ctx)?;
+        Ok(MfaService::enroll_totp(&current_user.user)
+            .await
+            .map_err(AppError::service_extend)?)
+    }
+
+    /// 提交验证码确认绑定, 返回恢复码 (只会显示这一次)
+    #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+    async fn confirm_totp(&self, ctx: &Context<'_>, code: String) -> GraphqlResult<Vec<String>> {
+        let current_user = current_user(ctx)?;
+        let result = MfaService::confirm_totp(&current_user.user.id, &code).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::MfaEnable)
+                .subject(&current_user.user.id)
+                .result(&result),
+        )
+        .await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 关闭两步验证, 需要提交验证码或恢复码
+    #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+    async fn disable_totp(&self, ctx: &Context<'_>, code: String) -> GraphqlResult<bool> {
+        let current_user = current_user(ctx)?;
+        let result = MfaService::disable_totp(&current_user.user.id, &code).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::MfaDisable)
+                .subject(&current_user.user.id)
+                .result(&result),
+        )
+        .await;
+
+        result.map_err(AppError::service_extend)?;
+        Ok(true)
+    }
+
+    /// 重新生成恢复码, 之前的恢复码全部失效
+    #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+    async fn regenerate_recovery_codes(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+    ) -> GraphqlResult<Vec<String>> {
+        let current_user = current_user(ctx)?;
+        let result = MfaService::regenerate_recovery_codes(&current_user.user.id, &code).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::RecoveryCodesRegenerate)
+                .subject(&current_user.user.id)
+                .result(&result),
+        )
+        .await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 登录时提交两步验证码 (或恢复码) 换取令牌
+    async fn verify_mfa(
+        &self,
+        ctx: &Context<'_>,
+        mfa_token: String,
+        code: String,
+    ) -> GraphqlResult<UsersToken> {
+        Ok(MfaService::verify_mfa(&mfa_token, &code, &client_info(ctx))
+            .await
+            .map_err(AppError::service_extend)?)
+    }
+}
+
+#[Object]
+impl AccessTokensMutation {
+    /// 创建个人访问令牌, 返回的令牌明文只会显示这一次
+    #[graphql(guard(and(SessionGuard(), RecentAuthGuard())))]
+    async fn create_access_token(
+        &self,
+        ctx: &Context<'_>,
+        vm: NewAccessToken,
+    ) -> GraphqlResult<CreatedAccessToken> {
+        vm.validate()
+            .map_err(AppError::RequestParameterError.validation_extend())?;
+        let current_user = current_user(ctx)?;
+        let result =
+            AccessTokensService::create(&current_user.user.id, &current_user.permissions, &vm)
+                .await;
+        let audit = audit_event(ctx, AuditAction::AccessTokenCreate)
+            .subject(&current_user.user.id)
+            .metadata("name", vm.name.as_str())
+            .metadata("scopes", vm.scopes.clone());
+        let audit = match &result {
+            Ok(created) => audit.metadata("token", created.access_token.id.to_string()),
+            Err(_) => audit,
+        };
+        AuditService::record(audit.result(&result)).await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 注销个人访问令牌, 令牌不存在时返回 false, 令牌泄露时需要尽快注销, 不要求重新验证密码
+    #[graphql(guard(SessionGuard()))]
+    async fn revoke_access_token(&self, ctx: &Context<'_>, id: Uuid) -> GraphqlResult<bool> {
+        let current_user = current_user(ctx)?;
+        let result = AccessTokensService::revoke(&id, &current_user.user.id).await;
+        // 令牌不存在时不记录
+        if !matches!(result, Ok(false)) {
+            AuditService::record(
+                audit_event(ctx, AuditAction::AccessTokenRevoke)
+                    .subject(&current_user.user.id)
+                    .metadata("token", id.to_string())
+                    .result(&result),
+            )
+            .await;
         }
 
-        // 密码哈希
-        let password_hash = CRYPTO.generate_password_hash(&new_user.password).await?;
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+}
 
-        let user = UsersService::user_register(&new_user, &password_hash).await?;
-        Ok(user)
+#[Object]
+impl AdminMutation {
+    /// 为用户分配角色, 已分配时返回 false
+    #[graphql(guard(and(
+        NotImpersonatingGuard(),
+        PermissionGuard(permission = "PERMISSION_ROLES_MANAGE")
+    )))]
+    async fn assign_role(
+        &self,
+        ctx: &Context<'_>,
+        user_id: Uuid,
+        role: String,
+    ) -> GraphqlResult<bool> {
+        let result = RolesService::assign(&user_id, &role).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::RoleAssign)
+                .subject(&user_id)
+                .metadata("role", role.as_str())
+                .result(&result),
+        )
+        .await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 解除账号的登录锁定
+    #[graphql(guard(and(
+        NotImpersonatingGuard(),
+        PermissionGuard(permission = "PERMISSION_USERS_MANAGE")
+    )))]
+    async fn unlock_account(&self, ctx: &Context<'_>, user_id: Uuid) -> GraphqlResult<bool> {
+        let result = LoginThrottle::clear(&LoginThrottle::account_key(&user_id.to_string())).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::AccountUnlock)
+                .subject(&user_id)
+                .result(&result),
+        )
+        .await;
+
+        let unlocked = result.map_err(AppError::InternalError.log_extend())?;
+        log::info!("用户: [{}] 的登录锁定已解除", user_id);
+        Ok(unlocked)
+    }
+
+    /// 代登录指定用户, 返回短期访问令牌, 代登录期间的请求都会记录到审计日志
+    #[graphql(guard(and(
+        SessionGuard(),
+        PermissionGuard(permission = "PERMISSION_USERS_IMPERSONATE")
+    )))]
+    async fn impersonate(
+        &self,
+        ctx: &Context<'_>,
+        user_id: Uuid,
+    ) -> GraphqlResult<ImpersonationToken> {
+        let current_user = current_user(ctx)?;
+        let result = AuthService::impersonate(&current_user.user.id, &user_id).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::Impersonate)
+                .subject(&user_id)
+                .result(&result),
+        )
+        .await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 移除用户的角色, 未分配时返回 false
+    #[graphql(guard(and(
+        NotImpersonatingGuard(),
+        PermissionGuard(permission = "PERMISSION_ROLES_MANAGE")
+    )))]
+    async fn remove_role(
+        &self,
+        ctx: &Context<'_>,
+        user_id: Uuid,
+        role: String,
+    ) -> GraphqlResult<bool> {
+        let result = RolesService::remove(&user_id, &role).await;
+        AuditService::record(
+            audit_event(ctx, AuditAction::RoleRemove)
+                .subject(&user_id)
+                .metadata("role", role.as_str())
+                .result(&result),
+        )
+        .await;
+
+        Ok(result.map_err(AppError::service_extend)?)
     }
 }
+
+#[test]
+fn test_admin_mutation_impersonation() {
+    crate::block_on_db(async {
+        use crate::security::auth::authenticate;
+        use crate::security::guards::ROLE_ADMIN;
+        use crate::service::users::create_test_user;
+        use crate::web::gql::queries::QueryRoot;
+
+        let schema = Schema::new(
+            QueryRoot::default(),
+            MutationRoot::default(),
+            EmptySubscription,
+        );
+        let admin = create_test_user("admin-9Kx2").await;
+        RolesService::assign(&admin.id, ROLE_ADMIN).await.unwrap();
+        let user = create_test_user("impersonated-9Kx2").await;
+        let token = AuthService::impersonate(&admin.id, &user.id).await.unwrap();
+        // 被代登录的用户即使拥有管理权限, 代登录期间也不能调用管理接口
+        let mut current_user = authenticate(&token.access_token).await.unwrap();
+        current_user
+            .permissions
+            .push(PERMISSION_ROLES_MANAGE.to_string());
+        let query = format!(
+            r#"mutation {{ assignRole(userId: "{}", role: "user") }}"#,
+            admin.id
+        );
+
+        let response = schema
+            .execute(Request::new(&query).data(current_user))
+            .await;
+        assert_eq!(
+            response.errors[0].message,
+            AppError::ImpersonationForbidden.to_string()
+        );
+
+        let mut current_user = authenticate(&token.access_token).await.unwrap();
+        current_user
+            .permissions
+            .push(PERMISSION_ROLES_MANAGE.to_string());
+        current_user.impersonator = None;
+        let response = schema
+            .execute(Request::new(&query).data(current_user))
+            .await;
+        assert!(response.errors.is_empty());
+    })
+}
+
+#[test]
+fn test_change_password_recent_auth() {
+    crate::block_on_db(async {
+        use chrono::Utc;
+
+        use crate::common::error::errors::is_app_error;
+        use crate::security::auth::{authenticate, ClientInfo};
+        use crate::security::crypto::TokenSubject;
+        use crate::security::guards::ROLE_USER;
+        use crate::service::users::create_test_user;
+        use crate::web::gql::queries::QueryRoot;
+        use crate::CONFIGS;
+
+        let schema = Schema::new(
+            QueryRoot::default(),
+            MutationRoot::default(),
+            EmptySubscription,
+        );
+        let user = create_test_user("recent-9Kx2-Vq7!").await;
+        let query = r#"mutation { changePassword(currentPassword: "recent-9Kx2-Vq7!", newPassword: "Recent-Mz4p-Wq8#") }"#;
+
+        // 超过有效期的令牌需要先重新验证密码
+        let window = CONFIGS.auth.get_reauthentication_window();
+        let stale = CRYPTO
+            .generate_jwt(&TokenSubject {
+                user_id: user.id,
+                sid: uuid::Uuid::new_v4(),
+                roles: vec![ROLE_USER.to_string()],
+                auth_time: Some(Utc::now() - window - chrono::Duration::minutes(1)),
+            })
+            .await
+            .unwrap();
+        let current_user = authenticate(&stale.access_token).await.unwrap();
+        let response = schema.execute(Request::new(query).data(current_user)).await;
+        assert_eq!(
+            response.errors[0].message,
+            AppError::ReauthenticationRequired.to_string()
+        );
+
+        // 重新验证密码后, 之前的刷新令牌不能再使用
+        let client = ClientInfo::default();
+        let before = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let current_user = authenticate(&before.access_token).await.unwrap();
+        let claims = current_user.claims().unwrap();
+        let after = AuthService::reauthenticate(&user, claims, "recent-9Kx2-Vq7!")
+            .await
+            .unwrap();
+        assert!(AuthService::refresh_tokens(&after.refash_token, &client)
+            .await
+            .is_ok());
+        let result = AuthService::refresh_tokens(&before.refash_token, &client).await;
+        assert!(is_app_error(&result, AppError::RefreshTokenReused));
+
+        // 刚登录的令牌在有效期内
+        let token = AuthService::issue_tokens(&user.id, &client).await.unwrap();
+        let current_user = authenticate(&token.access_token).await.unwrap();
+        let response = schema.execute(Request::new(query).data(current_user)).await;
+        assert!(response.errors.is_empty());
+    })
+}
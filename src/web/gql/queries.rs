@@ -1,12 +1,26 @@
+use async_graphql::guard::Guard;
 use async_graphql::*;
 use validator::Validate;
 
+use crate::security::auth::ClientInfo;
+use crate::security::guards::{
+    PermissionGuard, SessionGuard, PERMISSION_AUDIT_READ, PERMISSION_USERS_READ,
+};
+use crate::security::throttle::LoginThrottle;
+use crate::service::access_tokens::{AccessTokensService, ExtAccessTokensService};
+use crate::service::audit::{AuditService, ExtAuditService};
+use crate::service::auth::{AuthService, ExtAuthService};
+use crate::service::oidc::{ExtOidcService, OidcService};
 use crate::service::users::{ExtUsersService, UsersService};
-use crate::web::gql::GraphqlResult;
+use crate::web::gql::{client_info, current_user, GraphqlResult};
 use crate::{common::error::errors::AppError, domain::users::LoginVM, EMAIL_REGEX};
 use crate::{
-    domain::users::{TestValidator, Users, UsersToken},
-    CRYPTO,
+    domain::audit::{AuditAction, AuditEventFilter, AuditEventPage, NewAuditEvent, PageVM},
+    domain::mfa::SignInResult,
+    domain::oidc::OidcAuthorization,
+    domain::tokens::{PersonalAccessTokens, Sessions},
+    domain::users::{TestValidator, Users},
+    AUTH_PROVIDERS, CONFIGS, OIDC,
 };
 
 /// 定义查询根节点
@@ -28,58 +42,175 @@ impl PingQuery {
     }
 }
 
+impl UsersQuery {
+    /// 用户名密码登录, 确定登录的用户后记录到审计事件中
+    async fn password_sign_in(
+        login: &str,
+        password: &str,
+        client: &ClientInfo,
+        audit: &mut NewAuditEvent,
+    ) -> anyhow::Result<SignInResult> {
+        // 先判断用户登录方式
+        let users = if EMAIL_REGEX.is_match(login) {
+            UsersService::find_by_email(login).await?
+        } else {
+            UsersService::find_by_username(login).await?
+        };
+        audit.subject_id = users.as_ref().map(|users| users.id);
+
+        // 检查登录失败限制, 用户不存在时按登录名计数
+        let account_key = match &users {
+            Some(users) => LoginThrottle::account_key(&users.id.to_string()),
+            None => LoginThrottle::account_key(login),
+        };
+        let ip_key = client.ip.as_ref().map(LoginThrottle::ip_key);
+        let keys = std::iter::once(account_key.clone())
+            .chain(ip_key.clone())
+            .collect::<Vec<_>>();
+        LoginThrottle::check(&keys).await?;
+
+        // 依次使用配置的身份验证方式验证
+        let verify = AUTH_PROVIDERS.authenticate(login, password).await;
+        // 验证不通过或出现异常 (例如目录不可用) 都计入失败次数, 避免绕过登录失败限制
+        if !matches!(verify, Ok(Some(_))) {
+            LoginThrottle::record_sign_in_failure(&account_key, ip_key.as_deref()).await?;
+        }
+        let users = verify?.ok_or(AppError::UsernameOrPasswordError)?;
+        audit.subject_id = Some(users.id);
+        LoginThrottle::record_sign_in_success(&account_key, ip_key.as_deref()).await?;
+
+        // 要求验证邮箱
+        if CONFIGS.auth.require_email_verification && !users.email_verified {
+            return Err(AppError::EmailNotVerified.into());
+        }
+
+        AuthService::sign_in(&users.id, client).await
+    }
+
+    /// 第三方登录, 确定登录的用户后记录到审计事件中
+    async fn external_sign_in(
+        provider: &str,
+        code: &str,
+        state: &str,
+        client: &ClientInfo,
+        audit: &mut NewAuditEvent,
+    ) -> anyhow::Result<SignInResult> {
+        let users = OidcService::sign_in(provider, code, state).await?;
+        log::info!("用户: [{}] 通过: [{}] 验证通过", &users.username, provider);
+        audit.subject_id = Some(users.id);
+
+        // 要求验证邮箱
+        if CONFIGS.auth.require_email_verification && !users.email_verified {
+            return Err(AppError::EmailNotVerified.into());
+        }
+
+        AuthService::sign_in(&users.id, client).await
+    }
+
+    /// 登录结果的审计事件, 需要两步验证时标记为待验证
+    fn sign_in_audit(audit: NewAuditEvent, result: &anyhow::Result<SignInResult>) -> NewAuditEvent {
+        let audit = match result {
+            Ok(SignInResult::MfaChallenge(_)) => audit.metadata("mfa", "pending"),
+            _ => audit,
+        };
+        audit.result(result)
+    }
+}
+
 #[Object]
 impl UsersQuery {
     /// 用户登录
-    async fn user_sign_in(&self, vm: LoginVM) -> GraphqlResult<UsersToken> {
+    async fn user_sign_in(&self, ctx: &Context<'_>, vm: LoginVM) -> GraphqlResult<SignInResult> {
         // 参数校验
         vm.validate()
             .map_err(AppError::RequestParameterError.validation_extend())?;
         // 把登录名称处理为小写
         let login = vm.login.to_lowercase();
-        // 先判断用户登录方式
-        let is_email_login = EMAIL_REGEX.is_match(&login);
 
-        let users = if is_email_login {
-            UsersService::find_by_email(&login).await?
-        } else {
-            UsersService::find_by_username(&login).await?
-        };
+        let client = client_info(ctx);
+        let mut audit = NewAuditEvent::new(AuditAction::SignIn, &client).login(&login);
+        let result = Self::password_sign_in(&login, &vm.password, &client, &mut audit).await;
+        AuditService::record(Self::sign_in_audit(audit, &result)).await;
 
-        // 判断用户是否存在
-        let users = match users {
-            Some(users) => users,
-            None => return Err(AppError::UsernameOrPasswordError.extend()),
-        };
+        Ok(result.map_err(AppError::service_extend)?)
+    }
 
-        // 验证密码是否正确
-        let verify = CRYPTO
-            .verify_password(&vm.password, &users.password_hash)
-            .await;
-
-        // 处理验证结果
-        match verify {
-            // 验证出现异常
-            Err(_) => return Err(AppError::InternalError.extend()),
-            // 验证不通过
-            Ok(verify) if !verify => return Err(AppError::UsernameOrPasswordError.extend()),
-            // 验证通过
-            _ => log::info!("用户: [{}] 验证通过", &users.username),
-        };
+    /// 可以使用的第三方登录身份提供方
+    async fn oidc_providers(&self) -> GraphqlResult<Vec<String>> {
+        Ok(OIDC.names())
+    }
 
-        // todo 代码抽到 service 层去 这一次进做参数校验
-        let (access_token, refash_token, expires) = CRYPTO.generate_jwt(&users.id).await?;
+    /// 发起第三方登录, 返回身份提供方的授权地址
+    async fn oidc_authorize(&self, provider: String) -> GraphqlResult<OidcAuthorization> {
+        Ok(OidcService::authorize(&provider)
+            .await
+            .map_err(AppError::service_extend)?)
+    }
 
-        let users_token = UsersToken {
-            access_token,
-            refash_token,
-            expires: expires.num_seconds(),
-        };
+    /// 第三方登录回调, 使用授权码和 state 登录
+    async fn oidc_sign_in(
+        &self,
+        ctx: &Context<'_>,
+        provider: String,
+        code: String,
+        state: String,
+    ) -> GraphqlResult<SignInResult> {
+        let client = client_info(ctx);
+        let mut audit = NewAuditEvent::new(AuditAction::SignIn, &client)
+            .metadata("provider", provider.as_str());
+        let result = Self::external_sign_in(&provider, &code, &state, &client, &mut audit).await;
+        AuditService::record(Self::sign_in_audit(audit, &result)).await;
+
+        Ok(result.map_err(AppError::service_extend)?)
+    }
+
+    /// 当前登录用户
+    async fn me(&self, ctx: &Context<'_>) -> GraphqlResult<Users> {
+        Ok(current_user(ctx)?.user.clone())
+    }
 
-        Ok(users_token)
+    /// 当前用户已登录的会话 (设备)
+    #[graphql(guard(SessionGuard()))]
+    async fn my_sessions(&self, ctx: &Context<'_>) -> GraphqlResult<Vec<Sessions>> {
+        let current_user = current_user(ctx)?;
+        let claims = current_user
+            .claims()
+            .ok_or_else(|| AppError::Forbidden.extend())?;
+        Ok(
+            AuthService::find_sessions(&current_user.user.id, &claims.sid)
+                .await
+                .map_err(AppError::service_extend)?,
+        )
+    }
+
+    /// 当前用户的个人访问令牌
+    #[graphql(guard(SessionGuard()))]
+    async fn my_access_tokens(
+        &self,
+        ctx: &Context<'_>,
+    ) -> GraphqlResult<Vec<PersonalAccessTokens>> {
+        let current_user = current_user(ctx)?;
+        Ok(AccessTokensService::find_by_user(&current_user.user.id)
+            .await
+            .map_err(AppError::service_extend)?)
+    }
+
+    /// 分页查询审计日志, 按时间倒序
+    #[graphql(guard(PermissionGuard(permission = "PERMISSION_AUDIT_READ")))]
+    async fn audit_events(
+        &self,
+        #[graphql(default)] filter: AuditEventFilter,
+        #[graphql(default)] page: PageVM,
+    ) -> GraphqlResult<AuditEventPage> {
+        page.validate()
+            .map_err(AppError::RequestParameterError.validation_extend())?;
+        Ok(AuditService::find_page(&filter, &page)
+            .await
+            .map_err(AppError::service_extend)?)
     }
 
     /// 根据用户名查询用户
+    #[graphql(guard(PermissionGuard(permission = "PERMISSION_USERS_READ")))]
     async fn find_by_username(&self, username: String) -> GraphqlResult<Option<Users>> {
         Ok(UsersService::find_by_username(&username)
             .await
@@ -87,14 +218,21 @@ impl UsersQuery {
     }
 
     /// 根据用户名查询用户2
+    #[graphql(guard(PermissionGuard(permission = "PERMISSION_USERS_READ")))]
     async fn find_by_username2(&self, username: String) -> GraphqlResult<Users> {
         Ok(UsersService::find_by_username2(&username)
             .await
             .map_err(AppError::InternalError.log_extend())?)
     }
 
-    /// 检查用户名是否存在
-    async fn exists_by_username(&self, username: String) -> GraphqlResult<bool> {
+    /// 检查用户名是否存在, 需要在配置中开启, 按 IP 限制查询次数
+    async fn exists_by_username(&self, ctx: &Context<'_>, username: String) -> GraphqlResult<bool> {
+        if !CONFIGS.auth.existence_check.enabled {
+            return Err(AppError::Forbidden.extend());
+        }
+        LoginThrottle::record_existence_check(client_info(ctx).ip.as_ref())
+            .await
+            .map_err(AppError::service_extend)?;
         Ok(UsersService::exists_by_username(&username)
             .await
             .map_err(AppError::InternalError.log_extend())?)
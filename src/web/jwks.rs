@@ -0,0 +1,10 @@
+use warp::{Filter, Rejection, Reply};
+
+use crate::CRYPTO;
+
+// jwt 公钥入口, 下游服务使用其中的公钥验证令牌
+pub fn jwks() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!(".well-known" / "jwks.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&CRYPTO.jwks()))
+}
@@ -1 +1,2 @@
 pub mod gql;
+pub mod jwks;